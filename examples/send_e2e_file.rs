@@ -6,7 +6,7 @@ use std::process;
 
 use docopt::Docopt;
 use sodiumoxide::{self, crypto::secretbox};
-use threema_gateway::{ApiBuilder, FileMessage, RecipientKey, RenderingType};
+use threema_gateway::{ApiBuilder, FileMessage, Key, RenderingType};
 
 const USAGE: &str = "
 Usage: send_e2e_file [options] <from> <to> <secret> <private-key> <path-to-file> [<path-to-thumbnail>]
@@ -57,8 +57,7 @@ fn main() {
 
     // Fetch public key
     // Note: In a real application, you should cache the public key
-    let public_key = etry!(api.lookup_pubkey(to), "Could not fetch public key");
-    let recipient_key: RecipientKey = etry!(public_key.parse(), "Error");
+    let recipient_key = etry!(api.lookup_pubkey_typed(to), "Could not fetch public key");
 
     // Read files
     let mut file = etry!(File::open(filepath), "Could not open file");
@@ -107,24 +106,29 @@ fn main() {
         })
         .map(|blob_id| {
             let thumbnail_media_type =
-                mime_guess::from_path(&thumbpath.unwrap()).first_or_octet_stream();
+                mime_guess::from_path(thumbpath.unwrap()).first_or_octet_stream();
             (blob_id, thumbnail_media_type)
         });
 
     // Create file message
-    let file_media_type = mime_guess::from_path(&filepath).first_or_octet_stream();
+    let file_media_type = mime_guess::from_path(filepath).first_or_octet_stream();
     let file_name = filepath.file_name().and_then(OsStr::to_str);
-    let msg = FileMessage::builder(file_blob_id, key, file_media_type, file_data.len() as u32)
-        .thumbnail_opt(thumb_blob_id)
-        .file_name_opt(file_name)
-        .description("File message description")
-        .rendering_type(RenderingType::File)
-        .build()
-        .expect("Could not build FileMessage");
+    let msg = FileMessage::builder(
+        file_blob_id,
+        Key(key.0),
+        file_media_type,
+        file_data.len() as u32,
+    )
+    .thumbnail_opt(thumb_blob_id)
+    .file_name_opt(file_name)
+    .description("File message description")
+    .rendering_type(RenderingType::File)
+    .build()
+    .expect("Could not build FileMessage");
     let encrypted = api.encrypt_file_msg(&msg, &recipient_key);
 
     // Send
-    let msg_id = api.send(&to, &encrypted, false);
+    let msg_id = api.send(to, &encrypted, false);
     match msg_id {
         Ok(id) => println!("Sent. Message id is {}.", id),
         Err(e) => println!("Could not send message: {:?}", e),
@@ -0,0 +1,424 @@
+//! The NaCl implementation used for encryption.
+//!
+//! By default, libsodium is used (through the `sodiumoxide` crate). If the
+//! `rust-crypto` feature is enabled, the pure Rust implementation from the
+//! `crypto_box` crate is used instead. Both produce identical ciphertexts.
+//!
+//! libsodium is initialized lazily and exactly once, on the first use of any
+//! function of this module, so it never needs to be initialized manually.
+
+#[cfg(not(any(feature = "libsodium", feature = "rust-crypto")))]
+compile_error!("Either the \"libsodium\" or the \"rust-crypto\" feature must be enabled");
+
+#[cfg(all(feature = "libsodium", not(feature = "rust-crypto")))]
+mod imp {
+    use std::sync::Once;
+
+    use sodiumoxide::crypto::box_;
+    pub use sodiumoxide::crypto::box_::{PrecomputedKey, PublicKey, SecretKey};
+    use sodiumoxide::crypto::secretbox;
+    pub use sodiumoxide::crypto::secretbox::Key;
+    use sodiumoxide::crypto::stream::xsalsa20;
+
+    static INIT: Once = Once::new();
+
+    /// Initialize the library, if that has not happened yet. This is thread
+    /// safe.
+    pub(crate) fn init() {
+        INIT.call_once(|| {
+            sodiumoxide::init().expect("Could not initialize sodiumoxide library.");
+        });
+    }
+
+    /// Fill the buffer with random bytes.
+    pub(crate) fn randombytes_into(buf: &mut [u8]) {
+        init();
+        sodiumoxide::randombytes::randombytes_into(buf)
+    }
+
+    /// Generate a new random key pair.
+    pub(crate) fn gen_keypair() -> (PublicKey, SecretKey) {
+        init();
+        box_::gen_keypair()
+    }
+
+    /// Encrypt and authenticate the data (`crypto_box_easy`).
+    pub(crate) fn seal(
+        data: &[u8],
+        nonce: &[u8; 24],
+        public_key: &PublicKey,
+        private_key: &SecretKey,
+    ) -> Vec<u8> {
+        init();
+        box_::seal(data, &box_::Nonce(*nonce), public_key, private_key)
+    }
+
+    /// Verify and decrypt the data (`crypto_box_open_easy`).
+    pub(crate) fn open(
+        data: &[u8],
+        nonce: &[u8; 24],
+        public_key: &PublicKey,
+        private_key: &SecretKey,
+    ) -> Result<Vec<u8>, ()> {
+        init();
+        box_::open(data, &box_::Nonce(*nonce), public_key, private_key)
+    }
+
+    /// Precompute the shared key of a key pair (`crypto_box_beforenm`).
+    pub(crate) fn precompute(public_key: &PublicKey, private_key: &SecretKey) -> PrecomputedKey {
+        init();
+        box_::precompute(public_key, private_key)
+    }
+
+    /// Encrypt and authenticate the data with a precomputed key
+    /// (`crypto_box_easy_afternm`).
+    pub(crate) fn seal_precomputed(data: &[u8], nonce: &[u8; 24], key: &PrecomputedKey) -> Vec<u8> {
+        init();
+        box_::seal_precomputed(data, &box_::Nonce(*nonce), key)
+    }
+
+    /// Verify and decrypt the data with a precomputed key
+    /// (`crypto_box_open_easy_afternm`).
+    pub(crate) fn open_precomputed(
+        data: &[u8],
+        nonce: &[u8; 24],
+        key: &PrecomputedKey,
+    ) -> Result<Vec<u8>, ()> {
+        init();
+        box_::open_precomputed(data, &box_::Nonce(*nonce), key)
+    }
+
+    /// Encrypt and authenticate the data with a symmetric key
+    /// (`crypto_secretbox_easy`).
+    #[cfg(test)]
+    pub(crate) fn secretbox_seal(data: &[u8], nonce: &[u8; 24], key: &Key) -> Vec<u8> {
+        init();
+        secretbox::seal(data, &secretbox::Nonce(*nonce), key)
+    }
+
+    /// Verify and decrypt the data with a symmetric key in place
+    /// (`crypto_secretbox_open_detached`). On success, the buffer contains
+    /// the plaintext.
+    pub(crate) fn secretbox_open_in_place(
+        data: &mut Vec<u8>,
+        nonce: &[u8; 24],
+        key: &Key,
+    ) -> Result<(), ()> {
+        init();
+        if data.len() < secretbox::MACBYTES {
+            return Err(());
+        }
+        let tag = secretbox::Tag::from_slice(&data[..secretbox::MACBYTES]).ok_or(())?;
+        secretbox::open_detached(
+            &mut data[secretbox::MACBYTES..],
+            &tag,
+            &secretbox::Nonce(*nonce),
+            key,
+        )?;
+        data.drain(..secretbox::MACBYTES);
+        Ok(())
+    }
+
+    /// Encrypt or decrypt the data with the XSalsa20 stream cipher, without
+    /// authentication (`crypto_stream_xsalsa20_xor`).
+    pub(crate) fn stream_xor(data: &[u8], nonce: &[u8; 24], key: &[u8; 32]) -> Vec<u8> {
+        init();
+        xsalsa20::stream_xor(data, &xsalsa20::Nonce(*nonce), &xsalsa20::Key(*key))
+    }
+
+    /// Compare two byte slices in constant time.
+    pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+        init();
+        sodiumoxide::utils::memcmp(a, b)
+    }
+}
+
+#[cfg(feature = "rust-crypto")]
+mod imp {
+    use std::convert::TryInto;
+    use std::fmt;
+
+    use crypto_box::aead::rand_core::RngCore;
+    use crypto_box::aead::{Aead, OsRng};
+    use crypto_box::SalsaBox;
+    use crypto_secretbox::{AeadInPlace, KeyInit, Tag, XSalsa20Poly1305};
+    use salsa20::cipher::{KeyIvInit, StreamCipher};
+    use salsa20::XSalsa20;
+    use subtle::ConstantTimeEq;
+    use zeroize::Zeroize;
+
+    /// A Curve25519 public key.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct PublicKey(pub [u8; 32]);
+
+    impl PublicKey {
+        /// Create a `PublicKey` from a byte slice. It must contain 32 bytes.
+        pub fn from_slice(bs: &[u8]) -> Option<Self> {
+            bs.try_into().ok().map(PublicKey)
+        }
+    }
+
+    /// A Curve25519 secret key.
+    ///
+    /// Keys are compared in constant time and zeroized on drop.
+    #[derive(Clone)]
+    pub struct SecretKey(pub [u8; 32]);
+
+    impl SecretKey {
+        /// Create a `SecretKey` from a byte slice. It must contain 32 bytes.
+        pub fn from_slice(bs: &[u8]) -> Option<Self> {
+            bs.try_into().ok().map(SecretKey)
+        }
+
+        /// Compute the corresponding public key.
+        pub fn public_key(&self) -> PublicKey {
+            PublicKey(crypto_box::SecretKey::from(self.0).public_key().to_bytes())
+        }
+    }
+
+    impl PartialEq for SecretKey {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.ct_eq(&other.0).into()
+        }
+    }
+
+    impl Eq for SecretKey {}
+
+    impl Drop for SecretKey {
+        fn drop(&mut self) {
+            self.0.zeroize();
+        }
+    }
+
+    impl fmt::Debug for SecretKey {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "SecretKey(****)")
+        }
+    }
+
+    /// A symmetric XSalsa20-Poly1305 key.
+    ///
+    /// Keys are compared in constant time and zeroized on drop.
+    #[derive(Clone)]
+    pub struct Key(pub [u8; 32]);
+
+    impl Key {
+        /// Create a `Key` from a byte slice. It must contain 32 bytes.
+        pub fn from_slice(bs: &[u8]) -> Option<Self> {
+            bs.try_into().ok().map(Key)
+        }
+    }
+
+    impl PartialEq for Key {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.ct_eq(&other.0).into()
+        }
+    }
+
+    impl Eq for Key {}
+
+    impl Drop for Key {
+        fn drop(&mut self) {
+            self.0.zeroize();
+        }
+    }
+
+    impl fmt::Debug for Key {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Key(****)")
+        }
+    }
+
+    /// Fill the buffer with random bytes.
+    pub(crate) fn randombytes_into(buf: &mut [u8]) {
+        OsRng.fill_bytes(buf)
+    }
+
+    /// Generate a new random key pair.
+    pub(crate) fn gen_keypair() -> (PublicKey, SecretKey) {
+        let private_key = crypto_box::SecretKey::generate(&mut OsRng);
+        (
+            PublicKey(private_key.public_key().to_bytes()),
+            SecretKey(private_key.to_bytes()),
+        )
+    }
+
+    /// A precomputed shared key of a key pair.
+    ///
+    /// The key is zeroized on drop.
+    pub struct PrecomputedKey(SalsaBox);
+
+    impl fmt::Debug for PrecomputedKey {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "PrecomputedKey(****)")
+        }
+    }
+
+    /// Precompute the shared key of a key pair (`crypto_box_beforenm`).
+    pub(crate) fn precompute(public_key: &PublicKey, private_key: &SecretKey) -> PrecomputedKey {
+        PrecomputedKey(SalsaBox::new(
+            &crypto_box::PublicKey::from(public_key.0),
+            &crypto_box::SecretKey::from(private_key.0),
+        ))
+    }
+
+    /// Encrypt and authenticate the data (`crypto_box_easy`).
+    pub(crate) fn seal(
+        data: &[u8],
+        nonce: &[u8; 24],
+        public_key: &PublicKey,
+        private_key: &SecretKey,
+    ) -> Vec<u8> {
+        seal_precomputed(data, nonce, &precompute(public_key, private_key))
+    }
+
+    /// Verify and decrypt the data (`crypto_box_open_easy`).
+    pub(crate) fn open(
+        data: &[u8],
+        nonce: &[u8; 24],
+        public_key: &PublicKey,
+        private_key: &SecretKey,
+    ) -> Result<Vec<u8>, ()> {
+        open_precomputed(data, nonce, &precompute(public_key, private_key))
+    }
+
+    /// Encrypt and authenticate the data with a precomputed key
+    /// (`crypto_box_easy_afternm`).
+    pub(crate) fn seal_precomputed(data: &[u8], nonce: &[u8; 24], key: &PrecomputedKey) -> Vec<u8> {
+        key.0
+            .encrypt(nonce.into(), data)
+            .expect("Encryption failed")
+    }
+
+    /// Verify and decrypt the data with a precomputed key
+    /// (`crypto_box_open_easy_afternm`).
+    pub(crate) fn open_precomputed(
+        data: &[u8],
+        nonce: &[u8; 24],
+        key: &PrecomputedKey,
+    ) -> Result<Vec<u8>, ()> {
+        key.0.decrypt(nonce.into(), data).map_err(|_| ())
+    }
+
+    /// Encrypt and authenticate the data with a symmetric key
+    /// (`crypto_secretbox_easy`).
+    #[cfg(test)]
+    pub(crate) fn secretbox_seal(data: &[u8], nonce: &[u8; 24], key: &Key) -> Vec<u8> {
+        XSalsa20Poly1305::new(&key.0.into())
+            .encrypt(nonce.into(), data)
+            .expect("Encryption failed")
+    }
+
+    /// Verify and decrypt the data with a symmetric key in place
+    /// (`crypto_secretbox_open_detached`). On success, the buffer contains
+    /// the plaintext.
+    pub(crate) fn secretbox_open_in_place(
+        data: &mut Vec<u8>,
+        nonce: &[u8; 24],
+        key: &Key,
+    ) -> Result<(), ()> {
+        const TAG_BYTES: usize = 16;
+        if data.len() < TAG_BYTES {
+            return Err(());
+        }
+        let (tag, ciphertext) = data.split_at_mut(TAG_BYTES);
+        XSalsa20Poly1305::new(&key.0.into())
+            .decrypt_in_place_detached(nonce.into(), b"", ciphertext, Tag::from_slice(tag))
+            .map_err(|_| ())?;
+        data.drain(..TAG_BYTES);
+        Ok(())
+    }
+
+    /// Encrypt or decrypt the data with the XSalsa20 stream cipher, without
+    /// authentication (`crypto_stream_xsalsa20_xor`).
+    pub(crate) fn stream_xor(data: &[u8], nonce: &[u8; 24], key: &[u8; 32]) -> Vec<u8> {
+        let mut buf = data.to_vec();
+        XSalsa20::new(key.into(), nonce.into()).apply_keystream(&mut buf);
+        buf
+    }
+
+    /// Compare two byte slices in constant time.
+    pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+        a.ct_eq(b).into()
+    }
+}
+
+#[cfg(test)]
+pub(crate) use self::imp::secretbox_seal;
+pub(crate) use self::imp::{
+    ct_eq, gen_keypair, open, open_precomputed, precompute, randombytes_into, seal,
+    seal_precomputed, secretbox_open_in_place, stream_xor,
+};
+pub use self::imp::{Key, PrecomputedKey, PublicKey, SecretKey};
+
+/// Generate a random nonce.
+pub(crate) fn gen_nonce() -> [u8; 24] {
+    let mut nonce = [0; 24];
+    randombytes_into(&mut nonce);
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use data_encoding::HEXLOWER;
+
+    use super::*;
+
+    /// Known answer test, to ensure that all backends are interoperable.
+    #[test]
+    fn test_seal_open_vector() {
+        let private_key = SecretKey([1; 32]);
+        let public_key = SecretKey([2; 32]).public_key();
+        let nonce = [3; 24];
+        let ciphertext = seal(b"Hello, Threema!", &nonce, &public_key, &private_key);
+        assert_eq!(
+            HEXLOWER.encode(&ciphertext),
+            "3edf16c9f536dc0eefe8c066f718b05aab4e1363443a1afa4c72431217cbe1"
+        );
+        let plaintext = open(
+            &ciphertext,
+            &nonce,
+            &private_key.public_key(),
+            &SecretKey([2; 32]),
+        );
+        assert_eq!(plaintext.unwrap(), b"Hello, Threema!");
+    }
+
+    #[test]
+    fn test_open_tampered() {
+        let (a_pub, a_sec) = gen_keypair();
+        let (b_pub, b_sec) = gen_keypair();
+        let nonce = gen_nonce();
+        let mut ciphertext = seal(b"data", &nonce, &b_pub, &a_sec);
+        ciphertext[0] ^= 1;
+        assert!(open(&ciphertext, &nonce, &a_pub, &b_sec).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_first_use() {
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    let (public_key, private_key) = gen_keypair();
+                    let nonce = gen_nonce();
+                    let ciphertext = seal(b"data", &nonce, &public_key, &private_key);
+                    open(&ciphertext, &nonce, &public_key, &private_key).unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), b"data");
+        }
+    }
+
+    #[test]
+    fn test_precomputed() {
+        let (a_pub, a_sec) = gen_keypair();
+        let (b_pub, b_sec) = gen_keypair();
+        let nonce = gen_nonce();
+        let ciphertext = seal_precomputed(b"data", &nonce, &precompute(&b_pub, &a_sec));
+        assert_eq!(ciphertext, seal(b"data", &nonce, &b_pub, &a_sec));
+        let plaintext = open_precomputed(&ciphertext, &nonce, &precompute(&a_pub, &b_sec));
+        assert_eq!(plaintext.unwrap(), b"data");
+    }
+}
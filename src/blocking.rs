@@ -0,0 +1,1315 @@
+//! Blocking variants of the API objects.
+//!
+//! The blocking API objects wrap their async counterparts and drive them to
+//! completion on an internal single-threaded tokio runtime. They must not be
+//! used from within an async context.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{self, Read};
+use std::num::NonZeroU8;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures_util::stream;
+use mime::Mime;
+use reqwest::Body;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::api::{AsyncE2eApi, AsyncSimpleApi};
+use crate::connection::{MessageFlags, Recipient, SendOptions};
+use crate::crypto::{DecryptedMessage, EncryptedMessage, RecipientKey};
+use crate::errors::{ApiError, CryptoError};
+use crate::lookup::{Capabilities, CapabilityFlags, LookupCriterion};
+use crate::message_builder::MessageBuilder;
+use crate::receive::IncomingMessage;
+use crate::types::{
+    BallotMessage, BlobId, BlobUploadResult, Credits, DeliveryReceipt, DistributionList,
+    FileMessage, FsControlMessage, ImageMessage, MessageId,
+};
+use crate::{Key, PrecomputedKey, PublicKey};
+
+/// Create the runtime used to drive the async API objects.
+fn new_runtime() -> Arc<Runtime> {
+    let runtime = Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Could not create tokio runtime");
+    Arc::new(runtime)
+}
+
+/// Iterator over the chunks read from a reader.
+struct ReadChunks<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> Iterator for ReadChunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(n) => {
+                    buf.truncate(n);
+                    return Some(Ok(buf));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Implement methods available on both the simple and the e2e API objects.
+macro_rules! impl_common_functionality {
+    () => {
+        /// Run the future to completion on the internal runtime.
+        fn block_on<F: Future>(&self, future: F) -> F::Output {
+            self.runtime.block_on(future)
+        }
+
+        /// Fetch the public key for the specified Threema ID.
+        ///
+        /// For the end-to-end encrypted mode, you need the public key of the recipient
+        /// in order to encrypt a message. While it's best to obtain this directly from
+        /// the recipient (extract it from the QR code), this may not be convenient,
+        /// and therefore you can also look up the key associated with a given ID from
+        /// the server.
+        ///
+        /// It is strongly recommended that you cache the public keys to avoid querying
+        /// the API for each message.
+        pub fn lookup_pubkey(&self, id: &str) -> Result<String, ApiError> {
+            self.block_on(self.inner.lookup_pubkey(id))
+        }
+
+        /// Fetch the public key for the specified Threema ID, appending the
+        /// additional parameters to the query string.
+        ///
+        /// This is intended for API extensions (e.g. of OnPrem servers) that
+        /// require parameters not supported by this library. See
+        /// [`lookup_pubkey`](#method.lookup_pubkey).
+        pub fn lookup_pubkey_with_params(
+            &self,
+            id: &str,
+            additional_params: HashMap<String, String>,
+        ) -> Result<String, ApiError> {
+            self.block_on(self.inner.lookup_pubkey_with_params(id, additional_params))
+        }
+
+        /// Fetch the public key for the specified Threema ID and parse it
+        /// into a [`RecipientKey`](struct.RecipientKey.html).
+        ///
+        /// If the server returns invalid key material, an
+        /// [`ApiError::Other`](errors/enum.ApiError.html#variant.Other) is
+        /// returned.
+        pub fn lookup_pubkey_typed(&self, id: &str) -> Result<RecipientKey, ApiError> {
+            self.block_on(self.inner.lookup_pubkey_typed(id))
+        }
+
+        /// Fetch the public key for the specified Threema ID, using a cache.
+        ///
+        /// If the public key of this ID has been looked up before, it is
+        /// returned from the cache without querying the API. Otherwise, it is
+        /// fetched from the server and stored in the cache.
+        ///
+        /// The cache is shared between all clones of this API object. By
+        /// default, cache entries never expire. To set a TTL, use
+        /// [`ApiBuilder::with_pubkey_cache_ttl`](struct.ApiBuilder.html#method.with_pubkey_cache_ttl).
+        pub fn lookup_pubkey_cached(&self, id: &str) -> Result<RecipientKey, ApiError> {
+            self.block_on(self.inner.lookup_pubkey_cached(id))
+        }
+
+        /// Fetch the public keys for multiple Threema IDs.
+        ///
+        /// The keys are looked up like with
+        /// [`lookup_pubkey_cached`](#method.lookup_pubkey_cached), with up to
+        /// 8 lookups running concurrently. A failed lookup does not abort the
+        /// remaining lookups, instead the result for every ID is returned in
+        /// the same order as the IDs.
+        pub fn lookup_pubkeys(
+            &self,
+            ids: &[&str],
+        ) -> Vec<(String, Result<RecipientKey, ApiError>)> {
+            self.block_on(self.inner.lookup_pubkeys(ids))
+        }
+
+        /// Remove all entries from the public key cache.
+        pub fn clear_pubkey_cache(&self) {
+            self.inner.clear_pubkey_cache()
+        }
+
+        /// Look up a Threema ID in the directory.
+        ///
+        /// An ID can be looked up either by a phone number or an e-mail
+        /// address, in plaintext or hashed form. You can specify one of those
+        /// criteria using the [`LookupCriterion`](enum.LookupCriterion.html)
+        /// enum.
+        pub fn lookup_id(&self, criterion: &LookupCriterion) -> Result<String, ApiError> {
+            self.block_on(self.inner.lookup_id(criterion))
+        }
+
+        /// Look up a Threema ID in the directory, appending the additional
+        /// parameters to the query string.
+        ///
+        /// This is intended for API extensions (e.g. of OnPrem servers) that
+        /// require parameters not supported by this library. See
+        /// [`lookup_id`](#method.lookup_id).
+        pub fn lookup_id_with_params(
+            &self,
+            criterion: &LookupCriterion,
+            additional_params: HashMap<String, String>,
+        ) -> Result<String, ApiError> {
+            self.block_on(
+                self.inner
+                    .lookup_id_with_params(criterion, additional_params),
+            )
+        }
+
+        /// Look up a Threema ID in the directory, returning `None` if no ID
+        /// matches the criterion.
+        ///
+        /// Like [`lookup_id`](#method.lookup_id), but a missing match is
+        /// returned as `Ok(None)` instead of
+        /// [`ApiError::IdNotFound`](errors/enum.ApiError.html#variant.IdNotFound),
+        /// so that errors only signal real failures.
+        pub fn lookup_id_opt(
+            &self,
+            criterion: &LookupCriterion,
+        ) -> Result<Option<String>, ApiError> {
+            self.block_on(self.inner.lookup_id_opt(criterion))
+        }
+
+        /// Look up the Threema IDs for multiple phone numbers or email
+        /// addresses with a single request.
+        ///
+        /// The bulk lookup endpoint only accepts hashes, so plaintext criteria
+        /// are hashed first (see [`hashed_phone`](#method.hashed_phone) and
+        /// [`hashed_email`](#method.hashed_email)). The result contains one
+        /// entry per criterion, in the same order as the criteria. Criteria
+        /// without a matching ID result in `None`.
+        pub fn lookup_id_bulk(
+            &self,
+            criteria: &[LookupCriterion],
+        ) -> Result<Vec<Option<String>>, ApiError> {
+            self.block_on(self.inner.lookup_id_bulk(criteria))
+        }
+
+        /// Create a [`PhoneHash`](enum.LookupCriterion.html#variant.PhoneHash)
+        /// criterion from a phone number.
+        ///
+        /// The HMAC key configured with
+        /// [`ApiBuilder::with_onprem_config`](struct.ApiBuilder.html#method.with_onprem_config)
+        /// is used, or the key of the public Threema directory by default.
+        pub fn hashed_phone(&self, phone: &str) -> LookupCriterion {
+            self.inner.hashed_phone(phone)
+        }
+
+        /// Create an [`EmailHash`](enum.LookupCriterion.html#variant.EmailHash)
+        /// criterion from an email address.
+        ///
+        /// The HMAC key configured with
+        /// [`ApiBuilder::with_onprem_config`](struct.ApiBuilder.html#method.with_onprem_config)
+        /// is used, or the key of the public Threema directory by default.
+        pub fn hashed_email(&self, email: &str) -> LookupCriterion {
+            self.inner.hashed_email(email)
+        }
+
+        /// Look up the capabilities of a certain Threema ID.
+        ///
+        /// Before you send a file to a Threema ID using the blob upload (+file
+        /// message), you may want to check whether the recipient uses a
+        /// Threema version that supports receiving files. The receiver may be
+        /// using an old version, or a platform where file reception is not
+        /// supported.
+        pub fn lookup_capabilities(&self, id: &str) -> Result<Capabilities, ApiError> {
+            self.block_on(self.inner.lookup_capabilities(id))
+        }
+
+        /// Look up the capabilities of a certain Threema ID, appending the
+        /// additional parameters to the query string.
+        ///
+        /// This is intended for API extensions (e.g. of OnPrem servers) that
+        /// require parameters not supported by this library. See
+        /// [`lookup_capabilities`](#method.lookup_capabilities).
+        pub fn lookup_capabilities_with_params(
+            &self,
+            id: &str,
+            additional_params: HashMap<String, String>,
+        ) -> Result<Capabilities, ApiError> {
+            self.block_on(
+                self.inner
+                    .lookup_capabilities_with_params(id, additional_params),
+            )
+        }
+
+        /// Look up the remaining gateway credits.
+        pub fn lookup_credits(&self) -> Result<Credits, ApiError> {
+            self.block_on(self.inner.lookup_credits())
+        }
+
+        /// Look up the remaining gateway credits, appending the additional
+        /// parameters to the query string.
+        ///
+        /// This is intended for API extensions (e.g. of OnPrem servers) that
+        /// require parameters not supported by this library. See
+        /// [`lookup_credits`](#method.lookup_credits).
+        pub fn lookup_credits_with_params(
+            &self,
+            additional_params: HashMap<String, String>,
+        ) -> Result<Credits, ApiError> {
+            self.block_on(self.inner.lookup_credits_with_params(additional_params))
+        }
+
+        /// Replace the API secret.
+        ///
+        /// All subsequent requests use the new secret. The HTTP client and
+        /// the public key cache are kept, so this can be used to rotate the
+        /// secret of a long-running service.
+        ///
+        /// Only this instance is affected: existing clones keep using the old
+        /// secret, so the secret must be replaced on every clone (or the
+        /// clones must be created again after the rotation).
+        pub fn set_secret<S: Into<String>>(&mut self, secret: S) {
+            self.inner.set_secret(secret)
+        }
+
+        /// Return a clone of this API object that talks to a different API
+        /// endpoint.
+        ///
+        /// The clone shares the HTTP client and the public key cache with
+        /// this object. The API endpoint should be a HTTPS URL without trailing
+        /// slash.
+        pub fn with_endpoint<E: Into<Cow<'static, str>>>(&self, endpoint: E) -> Self {
+            Self {
+                inner: self.inner.with_endpoint(endpoint),
+                runtime: self.runtime.clone(),
+            }
+        }
+    };
+}
+
+/// Struct to talk to the simple API (without end-to-end encryption).
+///
+/// This is the blocking variant. For an async API, see
+/// [`AsyncSimpleApi`](struct.AsyncSimpleApi.html).
+///
+/// Prefer cloning over building new instances, see [Reusing API
+/// objects](index.html#reusing-api-objects).
+#[derive(Debug, Clone)]
+pub struct SimpleApi {
+    inner: AsyncSimpleApi,
+    runtime: Arc<Runtime>,
+}
+
+/// API objects are equal if they use the same credentials and endpoint. The
+/// HTTP client and the other settings are not compared.
+impl PartialEq for SimpleApi {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for SimpleApi {}
+
+impl SimpleApi {
+    /// Wrap an async simple API object.
+    pub(crate) fn new(inner: AsyncSimpleApi) -> Self {
+        SimpleApi {
+            inner,
+            runtime: new_runtime(),
+        }
+    }
+
+    /// Send a message to the specified recipient in basic mode.
+    ///
+    /// Note that this mode of sending messages does not provide end-to-end
+    /// encryption, only transport encryption between your host and the Threema
+    /// Gateway server.
+    ///
+    /// Cost: 1 credit.
+    pub fn send(&self, to: &Recipient<'_>, text: &str) -> Result<MessageId, ApiError> {
+        self.block_on(self.inner.send(to, text))
+    }
+
+    impl_common_functionality!();
+}
+
+/// Struct to talk to the E2E API (with end-to-end encryption).
+///
+/// This is the blocking variant. For an async API, see
+/// [`AsyncE2eApi`](struct.AsyncE2eApi.html).
+///
+/// Prefer cloning over building new instances, see [Reusing API
+/// objects](index.html#reusing-api-objects).
+#[derive(Debug, Clone)]
+pub struct E2eApi {
+    inner: AsyncE2eApi,
+    runtime: Arc<Runtime>,
+}
+
+/// API objects are equal if they use the same credentials, private key and
+/// endpoint. The HTTP client and the other settings are not compared.
+impl PartialEq for E2eApi {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for E2eApi {}
+
+impl E2eApi {
+    /// Wrap an async E2E API object.
+    pub(crate) fn new(inner: AsyncE2eApi) -> Self {
+        E2eApi {
+            inner,
+            runtime: new_runtime(),
+        }
+    }
+
+    /// Check whether the private key of this API object belongs to the
+    /// specified public key, e.g. the public key registered for the Gateway
+    /// ID (see [`lookup_pubkey`](#method.lookup_pubkey)).
+    ///
+    /// The public key is derived from the private key and compared in
+    /// constant time. A mismatch is logged as an error, since no message
+    /// encrypted with a wrong private key can be decrypted by the recipients.
+    pub fn verify_own_key(&self, expected_public_key: &PublicKey) -> bool {
+        self.inner.verify_own_key(expected_public_key)
+    }
+
+    /// Encrypt raw bytes for the specified recipient public key.
+    pub fn encrypt_raw(&self, data: &[u8], recipient_key: &RecipientKey) -> EncryptedMessage {
+        self.inner.encrypt_raw(data, recipient_key)
+    }
+
+    /// Encrypt raw bytes for the specified recipient public key, using the
+    /// specified nonce.
+    ///
+    /// Like [`encrypt_raw`](#method.encrypt_raw), this neither adds the
+    /// message type byte nor padding. A nonce must never be reused with the
+    /// same key pair.
+    pub fn encrypt_raw_with_nonce(
+        &self,
+        data: &[u8],
+        nonce: &[u8; 24],
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner
+            .encrypt_raw_with_nonce(data, nonce, recipient_key)
+    }
+
+    /// Return a [`MessageBuilder`] for messages to the specified recipient
+    /// public key.
+    ///
+    /// [`MessageBuilder`]: struct.MessageBuilder.html
+    pub fn message_builder<'a>(&'a self, recipient_key: &'a RecipientKey) -> MessageBuilder<'a> {
+        self.inner.message_builder(recipient_key)
+    }
+
+    /// Encrypt a text message for the specified recipient public key.
+    pub fn encrypt_text_msg(&self, text: &str, recipient_key: &RecipientKey) -> EncryptedMessage {
+        self.inner.encrypt_text_msg(text, recipient_key)
+    }
+
+    /// Encrypt a group text message for the specified recipient public key.
+    ///
+    /// The group is identified by the Threema ID of the group creator and the
+    /// 8 byte group ID. Group messages must be encrypted and sent to every
+    /// member of the group individually. If the group creator is not a valid
+    /// 8 character Threema ID, an error is returned.
+    pub fn encrypt_group_text_msg(
+        &self,
+        group_creator: &str,
+        group_id: &[u8; 8],
+        text: &str,
+        recipient_key: &RecipientKey,
+    ) -> Result<EncryptedMessage, CryptoError> {
+        self.inner
+            .encrypt_group_text_msg(group_creator, group_id, text, recipient_key)
+    }
+
+    /// Encrypt a group image message for the specified recipient public key.
+    ///
+    /// Before calling this function, you need to symmetrically encrypt the
+    /// image data (JPEG format, libsodium secretbox, random key, nonce
+    /// `000...1`) and upload the ciphertext to the blob server with
+    /// [`blob_upload_raw`](#method.blob_upload_raw).
+    ///
+    /// The group is identified by the Threema ID of the group creator and the
+    /// 8 byte group ID. Group messages must be encrypted and sent to every
+    /// member of the group individually. If the group creator is not a valid
+    /// 8 character Threema ID, an error is returned.
+    pub fn encrypt_group_image_msg(
+        &self,
+        group_creator: &str,
+        group_id: &[u8; 8],
+        blob_id: &BlobId,
+        img_size_bytes: u32,
+        blob_encryption_key: &Key,
+        recipient_key: &RecipientKey,
+    ) -> Result<EncryptedMessage, CryptoError> {
+        self.inner.encrypt_group_image_msg(
+            group_creator,
+            group_id,
+            blob_id,
+            img_size_bytes,
+            blob_encryption_key,
+            recipient_key,
+        )
+    }
+
+    /// Encrypt a group setup message for the specified recipient public key.
+    ///
+    /// The group setup message defines the members of a group (excluding the
+    /// group creator, which is this gateway ID). An empty member list
+    /// dissolves the group. The message must be sent to every current and
+    /// removed member individually. If a member is not a valid 8 character
+    /// Threema ID, an error is returned.
+    pub fn encrypt_group_setup_msg(
+        &self,
+        group_id: &[u8; 8],
+        members: &[&str],
+        recipient_key: &RecipientKey,
+    ) -> Result<EncryptedMessage, CryptoError> {
+        self.inner
+            .encrypt_group_setup_msg(group_id, members, recipient_key)
+    }
+
+    /// Encrypt a group rename message for the specified recipient public key.
+    ///
+    /// Like all group control messages, this may only be sent by the group
+    /// creator and must be sent to every member of the group individually.
+    pub fn encrypt_group_name_msg(
+        &self,
+        group_id: &[u8; 8],
+        name: &str,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner
+            .encrypt_group_name_msg(group_id, name, recipient_key)
+    }
+
+    /// Encrypt a group set photo message for the specified recipient public
+    /// key.
+    ///
+    /// Before calling this function, you need to symmetrically encrypt the
+    /// image data (JPEG format, libsodium secretbox, random key, nonce
+    /// `000...1`) and upload the ciphertext to the blob server with
+    /// [`blob_upload_raw`](#method.blob_upload_raw).
+    ///
+    /// Like all group control messages, this may only be sent by the group
+    /// creator and must be sent to every member of the group individually.
+    pub fn encrypt_group_set_photo_msg(
+        &self,
+        group_id: &[u8; 8],
+        blob_id: &BlobId,
+        img_size_bytes: u32,
+        blob_encryption_key: &Key,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_group_set_photo_msg(
+            group_id,
+            blob_id,
+            img_size_bytes,
+            blob_encryption_key,
+            recipient_key,
+        )
+    }
+
+    /// Encrypt a group delete photo message for the specified recipient
+    /// public key.
+    ///
+    /// Like all group control messages, this may only be sent by the group
+    /// creator and must be sent to every member of the group individually.
+    pub fn encrypt_group_delete_photo_msg(
+        &self,
+        group_id: &[u8; 8],
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner
+            .encrypt_group_delete_photo_msg(group_id, recipient_key)
+    }
+
+    /// Encrypt an image message for the specified recipient public key.
+    ///
+    /// Before calling this function, you need to encrypt the image data (JPEG
+    /// format) with [`encrypt_raw`](struct.E2eApi.html#method.encrypt_raw) and
+    /// upload the ciphertext to the blob server.
+    ///
+    /// The image size needs to be specified in bytes. Note that the size is
+    /// only used for download size displaying purposes and has no security
+    /// implications.
+    pub fn encrypt_image_msg(
+        &self,
+        blob_id: &BlobId,
+        img_size_bytes: u32,
+        image_data_nonce: &[u8; 24],
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner
+            .encrypt_image_msg(blob_id, img_size_bytes, image_data_nonce, recipient_key)
+    }
+
+    /// Encrypt an image message for the specified recipient public key,
+    /// using the specified nonce and amount of padding (in bytes) for the
+    /// message itself.
+    ///
+    /// The padding hides the length of the message, so the amount should be
+    /// chosen randomly (and stored, if the message needs to be re-encrypted
+    /// identically). A nonce must never be reused with the same key pair. Use
+    /// [`encrypt_image_msg`](#method.encrypt_image_msg) to encrypt a message
+    /// with a random nonce and padding.
+    pub fn encrypt_image_msg_with_nonce(
+        &self,
+        blob_id: &BlobId,
+        img_size_bytes: u32,
+        image_data_nonce: &[u8; 24],
+        nonce: &[u8; 24],
+        padding_amount: NonZeroU8,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_image_msg_with_nonce(
+            blob_id,
+            img_size_bytes,
+            image_data_nonce,
+            nonce,
+            padding_amount,
+            recipient_key,
+        )
+    }
+
+    /// Encrypt an [`ImageMessage`] for the specified recipient public key.
+    ///
+    /// This is equivalent to [`encrypt_image_msg`](#method.encrypt_image_msg),
+    /// but takes the message fields from an [`ImageMessage`], which can be
+    /// constructed with [`ImageMessageBuilder`].
+    ///
+    /// [`ImageMessage`]: struct.ImageMessage.html
+    /// [`ImageMessageBuilder`]: struct.ImageMessageBuilder.html
+    pub fn encrypt_image_message(
+        &self,
+        msg: &ImageMessage,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_image_message(msg, recipient_key)
+    }
+
+    /// Encrypt an audio message for the specified recipient public key.
+    ///
+    /// Before calling this function, you need to symmetrically encrypt the
+    /// audio data (libsodium secretbox, random key, nonce `000...1`) and upload
+    /// the ciphertext to the blob server with
+    /// [`blob_upload_raw`](#method.blob_upload_raw).
+    ///
+    /// The duration (in seconds) is displayed by the recipient's client. The
+    /// audio size needs to be specified in bytes. Note that the size is only
+    /// used for download size displaying purposes and has no security
+    /// implications.
+    pub fn encrypt_audio_msg(
+        &self,
+        blob_id: &BlobId,
+        duration_seconds: u16,
+        audio_size_bytes: u32,
+        blob_encryption_key: &Key,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_audio_msg(
+            blob_id,
+            duration_seconds,
+            audio_size_bytes,
+            blob_encryption_key,
+            recipient_key,
+        )
+    }
+
+    /// Encrypt a video message for the specified recipient public key.
+    ///
+    /// Before calling this function, you need to symmetrically encrypt the
+    /// video data and the thumbnail (JPEG) with the same key (libsodium
+    /// secretbox, random key, nonce `000...1` for the video and `000...2` for
+    /// the thumbnail) and upload both ciphertexts to the blob server with
+    /// [`blob_upload_raw`](#method.blob_upload_raw).
+    ///
+    /// The duration (in seconds) is displayed by the recipient's client. The
+    /// sizes need to be specified in bytes. Note that the sizes are only used
+    /// for download size displaying purposes and have no security
+    /// implications.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encrypt_video_msg(
+        &self,
+        blob_id: &BlobId,
+        thumbnail_blob_id: &BlobId,
+        duration_seconds: u16,
+        video_size_bytes: u32,
+        thumbnail_size_bytes: u32,
+        blob_encryption_key: &Key,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_video_msg(
+            blob_id,
+            thumbnail_blob_id,
+            duration_seconds,
+            video_size_bytes,
+            thumbnail_size_bytes,
+            blob_encryption_key,
+            recipient_key,
+        )
+    }
+
+    /// Encrypt a location message for the specified recipient public key.
+    ///
+    /// The latitude must be in the range `[-90, 90]` and the longitude in
+    /// the range `[-180, 180]`, otherwise an error is returned. The accuracy
+    /// is specified in meters. The optional name (e.g. an address) is
+    /// displayed along with the location.
+    pub fn encrypt_location_msg(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        accuracy: Option<f64>,
+        name: Option<&str>,
+        recipient_key: &RecipientKey,
+    ) -> Result<EncryptedMessage, CryptoError> {
+        self.inner
+            .encrypt_location_msg(latitude, longitude, accuracy, name, recipient_key)
+    }
+
+    /// Encrypt a file message for the specified recipient public key.
+    ///
+    /// To construct a [`FileMessage`], use [`FileMessageBuilder`].
+    ///
+    /// [`FileMessage`]: struct.FileMessage.html
+    /// [`FileMessageBuilder`]: struct.FileMessageBuilder.html
+    pub fn encrypt_file_msg(
+        &self,
+        msg: &FileMessage,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_file_msg(msg, recipient_key)
+    }
+
+    /// Encrypt a file message for the specified recipient public key, using
+    /// the specified nonce and amount of padding (in bytes).
+    ///
+    /// The padding hides the length of the message, so the amount should be
+    /// chosen randomly (and stored, if the message needs to be re-encrypted
+    /// identically). A nonce must never be reused with the same key pair. Use
+    /// [`encrypt_file_msg`](#method.encrypt_file_msg) to encrypt a message
+    /// with a random nonce and padding.
+    pub fn encrypt_file_msg_with_nonce(
+        &self,
+        msg: &FileMessage,
+        nonce: &[u8; 24],
+        padding_amount: NonZeroU8,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner
+            .encrypt_file_msg_with_nonce(msg, nonce, padding_amount, recipient_key)
+    }
+
+    /// Encrypt a ballot (poll) create message for the specified recipient
+    /// public key.
+    ///
+    /// To construct a [`BallotMessage`], use [`BallotMessageBuilder`].
+    ///
+    /// [`BallotMessage`]: struct.BallotMessage.html
+    /// [`BallotMessageBuilder`]: struct.BallotMessageBuilder.html
+    pub fn encrypt_ballot_create_msg(
+        &self,
+        msg: &BallotMessage,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_ballot_create_msg(msg, recipient_key)
+    }
+
+    /// Encrypt a delivery receipt for the specified recipient public key.
+    ///
+    /// A delivery receipt confirms the reception (or reading, acknowledgement,
+    /// etc.) of one or more messages sent by the recipient.
+    pub fn encrypt_delivery_receipt(
+        &self,
+        receipt: &DeliveryReceipt,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_delivery_receipt(receipt, recipient_key)
+    }
+
+    /// Encrypt a forward security control message for the specified
+    /// recipient public key.
+    ///
+    /// Only the control messages can be constructed, sessions must be
+    /// managed by the caller. See [`FsControlMessage`] for details on what
+    /// is not supported.
+    ///
+    /// [`FsControlMessage`]: enum.FsControlMessage.html
+    pub fn encrypt_fs_control(
+        &self,
+        msg: &FsControlMessage,
+        recipient_key: &RecipientKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_fs_control(msg, recipient_key)
+    }
+
+    /// Decrypt an incoming message from the specified sender public key.
+    ///
+    /// The padding is validated and removed, and the payload is parsed
+    /// according to the message type byte.
+    pub fn decrypt(
+        &self,
+        box_data: &[u8],
+        nonce: &[u8; 24],
+        sender_key: &RecipientKey,
+    ) -> Result<DecryptedMessage, CryptoError> {
+        self.inner.decrypt(box_data, nonce, sender_key)
+    }
+
+    /// Decrypt an incoming text message from the specified sender public key,
+    /// replacing invalid UTF-8 sequences with the replacement character
+    /// `U+FFFD`.
+    ///
+    /// [`decrypt`](#method.decrypt) returns an error for text messages that
+    /// are not valid UTF-8. Use this method to decrypt them anyway. If the
+    /// message is not a text message, an error is returned.
+    pub fn decrypt_text_lossy(
+        &self,
+        box_data: &[u8],
+        nonce: &[u8; 24],
+        sender_key: &RecipientKey,
+    ) -> Result<String, CryptoError> {
+        self.inner.decrypt_text_lossy(box_data, nonce, sender_key)
+    }
+
+    /// Precompute the shared key of the specified recipient public key and
+    /// the private key of this API object.
+    ///
+    /// When sending many messages to (or receiving many messages from) the
+    /// same ID, encrypting with a precomputed key avoids repeating the key
+    /// exchange for every message. The precomputed key is secret and is
+    /// zeroized on drop.
+    pub fn precompute(&self, recipient_key: &RecipientKey) -> PrecomputedKey {
+        self.inner.precompute(recipient_key)
+    }
+
+    /// Encrypt raw bytes with a key returned by
+    /// [`precompute`](#method.precompute).
+    pub fn encrypt_raw_precomputed(&self, data: &[u8], key: &PrecomputedKey) -> EncryptedMessage {
+        self.inner.encrypt_raw_precomputed(data, key)
+    }
+
+    /// Encrypt a text message with a key returned by
+    /// [`precompute`](#method.precompute).
+    pub fn encrypt_text_msg_precomputed(
+        &self,
+        text: &str,
+        key: &PrecomputedKey,
+    ) -> EncryptedMessage {
+        self.inner.encrypt_text_msg_precomputed(text, key)
+    }
+
+    /// Decrypt an incoming message with a key returned by
+    /// [`precompute`](#method.precompute).
+    ///
+    /// See [`decrypt`](#method.decrypt) for details.
+    pub fn decrypt_precomputed(
+        &self,
+        box_data: &[u8],
+        nonce: &[u8; 24],
+        key: &PrecomputedKey,
+    ) -> Result<DecryptedMessage, CryptoError> {
+        self.inner.decrypt_precomputed(box_data, nonce, key)
+    }
+
+    /// Verify the MAC of an incoming message, using the API secret.
+    ///
+    /// See [`IncomingMessage::verify_mac`](struct.IncomingMessage.html#method.verify_mac)
+    /// for details.
+    pub fn verify_mac(&self, incoming: &IncomingMessage) -> Result<(), ApiError> {
+        self.inner.verify_mac(incoming)
+    }
+
+    /// Send an encrypted E2E message to the specified Threema ID.
+    ///
+    /// If `delivery_receipts` is set to `false`, then the recipient's device will
+    /// be instructed not to send any delivery receipts. This can be useful for
+    /// one-way communication where the delivery receipt will be discarded. If
+    /// you're unsure what value to use, set the flag to `false`.
+    ///
+    /// If `to` is not a well-formed Threema ID, an
+    /// [`ApiError::BadSenderOrRecipient`](errors/enum.ApiError.html#variant.BadSenderOrRecipient)
+    /// is returned without sending a request.
+    ///
+    /// Cost: 1 credit.
+    pub fn send(
+        &self,
+        to: &str,
+        message: &EncryptedMessage,
+        delivery_receipts: bool,
+    ) -> Result<MessageId, ApiError> {
+        self.block_on(self.inner.send(to, message, delivery_receipts))
+    }
+
+    /// Send an encrypted E2E message to the specified Threema ID, using the
+    /// specified [`SendOptions`](struct.SendOptions.html), but only if the
+    /// recipient has all `required` capabilities.
+    ///
+    /// The capabilities are looked up first. If a capability is missing, no
+    /// message is sent and an
+    /// [`ApiError::Other`](errors/enum.ApiError.html#variant.Other) naming the
+    /// missing capabilities is returned. Otherwise the ID of the sent message
+    /// is returned together with the capabilities of the recipient.
+    ///
+    /// This avoids spending a credit on a recipient whose client cannot
+    /// display the message, e.g. a file message.
+    ///
+    /// Cost: 1 credit if the message is sent (plus the capability lookup).
+    pub fn send_if_capable(
+        &self,
+        to: &str,
+        message: &EncryptedMessage,
+        options: &SendOptions,
+        required: CapabilityFlags,
+    ) -> Result<(MessageId, Capabilities), ApiError> {
+        self.block_on(self.inner.send_if_capable(to, message, options, required))
+    }
+
+    /// Send encrypted E2E messages to multiple Threema IDs.
+    ///
+    /// Each entry consists of the recipient Threema ID and the message
+    /// encrypted for that recipient. The messages are sent one after another.
+    /// A failure to send to one recipient does not abort sending to the
+    /// remaining recipients, instead the result for every recipient is
+    /// returned in the same order.
+    ///
+    /// See [`send`](#method.send) for the meaning of `delivery_receipts`.
+    ///
+    /// Cost: 1 credit per message.
+    pub fn send_batch(
+        &self,
+        recipients: &[(&str, &EncryptedMessage)],
+        delivery_receipts: bool,
+    ) -> Vec<(String, Result<MessageId, ApiError>)> {
+        self.block_on(self.inner.send_batch(recipients, delivery_receipts))
+    }
+
+    /// Encrypt and send a text message to multiple Threema IDs.
+    ///
+    /// Each entry consists of the recipient Threema ID and its public key.
+    /// The text is encrypted separately for every recipient and sent like
+    /// with [`send_batch`](#method.send_batch): A failure to send to one
+    /// recipient does not abort sending to the remaining recipients, instead
+    /// the result for every recipient is returned in the same order.
+    ///
+    /// See [`send`](#method.send) for the meaning of `delivery_receipts`.
+    ///
+    /// Cost: 1 credit per recipient.
+    pub fn broadcast_text(
+        &self,
+        recipients: &[(&str, &RecipientKey)],
+        text: &str,
+        delivery_receipts: bool,
+    ) -> Vec<(String, Result<MessageId, ApiError>)> {
+        self.block_on(
+            self.inner
+                .broadcast_text(recipients, text, delivery_receipts),
+        )
+    }
+
+    /// Encrypt and send a text message to all members of a
+    /// [`DistributionList`](struct.DistributionList.html).
+    ///
+    /// The text is encrypted separately for every member and sent like with
+    /// [`broadcast_text`](#method.broadcast_text). The result for every
+    /// member is returned in the order of the list, so failures can be
+    /// handled per member.
+    ///
+    /// See [`send`](#method.send) for the meaning of `delivery_receipts`.
+    ///
+    /// Cost: 1 credit per member.
+    pub fn send_text_to_list(
+        &self,
+        list: &DistributionList,
+        text: &str,
+        delivery_receipts: bool,
+    ) -> Vec<(String, Result<MessageId, ApiError>)> {
+        self.block_on(self.inner.send_text_to_list(list, text, delivery_receipts))
+    }
+
+    /// Encrypt and send a text message to the specified Threema ID.
+    ///
+    /// This is a shortcut for looking up the public key of the recipient
+    /// (using the cache, see [`lookup_pubkey_cached`](#method.lookup_pubkey_cached)),
+    /// encrypting the text with
+    /// [`encrypt_text_msg`](#method.encrypt_text_msg) and sending it with
+    /// [`send`](#method.send). Delivery receipts are disabled.
+    ///
+    /// Cost: 1 credit (plus the public key lookup, if the key is not cached).
+    pub fn send_text(&self, to: &str, text: &str) -> Result<MessageId, ApiError> {
+        self.block_on(self.inner.send_text(to, text))
+    }
+
+    /// Encrypt and send a text message of arbitrary length to the specified
+    /// Threema ID.
+    ///
+    /// Like [`send_text`](#method.send_text), but texts that are longer than
+    /// [`MAX_TEXT_LENGTH`](constant.MAX_TEXT_LENGTH.html) are split into
+    /// multiple messages using [`split_text`](fn.split_text.html). The IDs of
+    /// all sent messages are returned. Sending stops at the first error.
+    ///
+    /// Cost: 1 credit per message (plus the public key lookup, if the key is
+    /// not cached).
+    pub fn send_text_split(&self, to: &str, text: &str) -> Result<Vec<MessageId>, ApiError> {
+        self.block_on(self.inner.send_text_split(to, text))
+    }
+
+    /// Resolve a recipient to a Threema ID.
+    ///
+    /// A Threema ID is returned as is. A phone number or e-mail address is
+    /// looked up in the directory with [`lookup_id`](#method.lookup_id).
+    /// If the recipient is not well-formed, an
+    /// [`ApiError::BadSenderOrRecipient`](errors/enum.ApiError.html#variant.BadSenderOrRecipient)
+    /// is returned without sending a request.
+    pub fn resolve_recipient(&self, to: &Recipient<'_>) -> Result<String, ApiError> {
+        self.block_on(self.inner.resolve_recipient(to))
+    }
+
+    /// Encrypt and send a text message to the specified recipient.
+    ///
+    /// Like [`send_text`](#method.send_text), but the recipient can also be
+    /// specified by phone number or e-mail address, like with the simple API.
+    /// Since the message must be encrypted with the public key of the
+    /// recipient, the recipient is first resolved to a Threema ID (see
+    /// [`resolve_recipient`](#method.resolve_recipient)) and its public key is
+    /// looked up.
+    ///
+    /// Cost: 1 credit (plus the lookups, if the ID or key is not known).
+    pub fn send_text_to_recipient(
+        &self,
+        to: &Recipient<'_>,
+        text: &str,
+    ) -> Result<MessageId, ApiError> {
+        self.block_on(self.inner.send_text_to_recipient(to, text))
+    }
+
+    /// Upload an image file and send it as an image message to the
+    /// specified Threema ID.
+    ///
+    /// The file (which should be in JPEG format) is read, encrypted with
+    /// [`encrypt_raw`](#method.encrypt_raw) and uploaded to the blob server.
+    /// Then an image message referencing the blob is encrypted with
+    /// [`encrypt_image_msg`](#method.encrypt_image_msg) and sent with
+    /// [`send`](#method.send). The image size in the message is the size of
+    /// the file in bytes. Delivery receipts are disabled.
+    ///
+    /// Cost: 2 credits (1 for the blob upload, 1 for the message).
+    pub fn send_image_file(
+        &self,
+        to: &str,
+        path: &Path,
+        recipient_key: &RecipientKey,
+    ) -> Result<MessageId, ApiError> {
+        self.block_on(self.inner.send_image_file(to, path, recipient_key))
+    }
+
+    /// Send an encrypted E2E message to the specified Threema ID, using the
+    /// specified [`MessageFlags`](struct.MessageFlags.html).
+    ///
+    /// See [`SendOptions::from_flags`](struct.SendOptions.html#method.from_flags)
+    /// for the supported flags. [`send`](#method.send) is equivalent to
+    /// sending with `MessageFlags::PUSH`, plus `NO_DELIVERY_RECEIPTS` if
+    /// delivery receipts are disabled.
+    ///
+    /// Cost: 1 credit.
+    pub fn send_with_flags(
+        &self,
+        to: &str,
+        message: &EncryptedMessage,
+        flags: MessageFlags,
+    ) -> Result<MessageId, ApiError> {
+        self.block_on(self.inner.send_with_flags(to, message, flags))
+    }
+
+    /// Send an already encrypted E2E message, given as nonce and ciphertext
+    /// (box), to the specified Threema ID.
+    ///
+    /// Like [`send`](#method.send), but without an
+    /// [`EncryptedMessage`](struct.EncryptedMessage.html), e.g. for messages
+    /// that were encrypted earlier and stored as separate parts.
+    ///
+    /// Cost: 1 credit.
+    pub fn send_raw(
+        &self,
+        to: &str,
+        nonce: &[u8; 24],
+        box_data: &[u8],
+        delivery_receipts: bool,
+    ) -> Result<MessageId, ApiError> {
+        self.block_on(self.inner.send_raw(to, nonce, box_data, delivery_receipts))
+    }
+
+    /// Send an encrypted E2E message to the specified Threema ID, using the
+    /// specified [`SendOptions`](struct.SendOptions.html).
+    ///
+    /// If `to` is not a well-formed Threema ID, an
+    /// [`ApiError::BadSenderOrRecipient`](errors/enum.ApiError.html#variant.BadSenderOrRecipient)
+    /// is returned without sending a request.
+    ///
+    /// Cost: 1 credit.
+    pub fn send_with_options(
+        &self,
+        to: &str,
+        message: &EncryptedMessage,
+        options: &SendOptions,
+    ) -> Result<MessageId, ApiError> {
+        self.block_on(self.inner.send_with_options(to, message, options))
+    }
+
+    /// Used for testing purposes. Not intended to be called by end users.
+    #[doc(hidden)]
+    pub fn send_with_params(
+        &self,
+        to: &str,
+        message: &EncryptedMessage,
+        delivery_receipts: bool,
+        additional_params: HashMap<String, String>,
+    ) -> Result<MessageId, ApiError> {
+        self.block_on(self.inner.send_with_params(
+            to,
+            message,
+            delivery_receipts,
+            additional_params,
+        ))
+    }
+
+    impl_common_functionality!();
+
+    /// Upload encrypted data to the blob server.
+    ///
+    /// If `persist` is set to `true`, then the blob will not be deleted
+    /// after a client has downloaded it and marked it as done. Use when
+    /// distributing the same blob to multiple clients.
+    ///
+    /// Cost: 1 credit.
+    pub fn blob_upload(&self, data: &EncryptedMessage, persist: bool) -> Result<BlobId, ApiError> {
+        self.block_on(self.inner.blob_upload(data, persist))
+    }
+
+    /// Upload encrypted data to the blob server and return the blob ID
+    /// together with the expiry time of the blob.
+    ///
+    /// The expiry time is only known if the server returns an `Expires`
+    /// header, otherwise it is `None`. See [`blob_upload`](#method.blob_upload)
+    /// for the meaning of `persist`.
+    ///
+    /// Cost: 1 credit.
+    pub fn blob_upload_with_expiry(
+        &self,
+        data: &EncryptedMessage,
+        persist: bool,
+    ) -> Result<BlobUploadResult, ApiError> {
+        self.block_on(self.inner.blob_upload_with_expiry(data, persist))
+    }
+
+    /// Upload encrypted data to the blob server, setting the specified media
+    /// type (e.g. `application/pdf`) in the multipart part header.
+    ///
+    /// The other upload methods use `application/octet-stream`. See
+    /// [`blob_upload`](#method.blob_upload) for the meaning of `persist`.
+    ///
+    /// Cost: 1 credit.
+    pub fn blob_upload_with_media_type(
+        &self,
+        data: &EncryptedMessage,
+        persist: bool,
+        media_type: &Mime,
+    ) -> Result<BlobId, ApiError> {
+        self.block_on(
+            self.inner
+                .blob_upload_with_media_type(data, persist, media_type),
+        )
+    }
+
+    /// Used for testing purposes. Not intended to be called by end users.
+    #[doc(hidden)]
+    pub fn blob_upload_with_params(
+        &self,
+        data: &EncryptedMessage,
+        persist: bool,
+        additional_params: HashMap<String, String>,
+    ) -> Result<BlobId, ApiError> {
+        self.block_on(
+            self.inner
+                .blob_upload_with_params(data, persist, additional_params),
+        )
+    }
+
+    /// Upload raw data to the blob server.
+    ///
+    /// If `persist` is set to `true`, then the blob will not be deleted
+    /// after a client has downloaded it and marked it as done. Use when
+    /// distributing the same blob to multiple clients.
+    ///
+    /// Cost: 1 credit.
+    pub fn blob_upload_raw(&self, data: &[u8], persist: bool) -> Result<BlobId, ApiError> {
+        self.block_on(self.inner.blob_upload_raw(data, persist))
+    }
+
+    /// Upload raw data to the blob server, setting the specified media type
+    /// (e.g. `application/pdf`) in the multipart part header.
+    ///
+    /// The other upload methods use `application/octet-stream`. See
+    /// [`blob_upload_raw`](#method.blob_upload_raw) for the meaning of
+    /// `persist`.
+    ///
+    /// Cost: 1 credit.
+    pub fn blob_upload_raw_with_media_type(
+        &self,
+        data: &[u8],
+        persist: bool,
+        media_type: &Mime,
+    ) -> Result<BlobId, ApiError> {
+        self.block_on(
+            self.inner
+                .blob_upload_raw_with_media_type(data, persist, media_type),
+        )
+    }
+
+    /// Used for testing purposes. Not intended to be called by end users.
+    #[doc(hidden)]
+    pub fn blob_upload_raw_with_params(
+        &self,
+        data: &[u8],
+        persist: bool,
+        additional_params: HashMap<String, String>,
+    ) -> Result<BlobId, ApiError> {
+        self.block_on(
+            self.inner
+                .blob_upload_raw_with_params(data, persist, additional_params),
+        )
+    }
+
+    /// Upload raw data to the blob server, streaming it from the specified
+    /// reader instead of buffering it in memory.
+    ///
+    /// The length of the data in bytes must be specified in advance. Use this
+    /// for large files. Note that streaming uploads are never retried, even
+    /// if a retry policy is configured.
+    ///
+    /// If `persist` is set to `true`, then the blob will not be deleted
+    /// after a client has downloaded it and marked it as done. Use when
+    /// distributing the same blob to multiple clients.
+    ///
+    /// Cost: 1 credit.
+    pub fn blob_upload_stream<R>(
+        &self,
+        reader: R,
+        len: u64,
+        persist: bool,
+    ) -> Result<BlobId, ApiError>
+    where
+        R: Read + Send + Sync + 'static,
+    {
+        let chunks = ReadChunks {
+            reader,
+            done: false,
+        };
+        let body = Body::wrap_stream(stream::iter(chunks));
+        self.block_on(self.inner.blob_upload_body(body, len, persist))
+    }
+
+    /// Check whether a blob exists on the blob server, without downloading
+    /// it.
+    ///
+    /// Returns `false` if the blob cannot be found or has expired. Other
+    /// errors are returned like with [`blob_download`](#method.blob_download).
+    pub fn blob_exists(&self, blob_id: &BlobId) -> Result<bool, ApiError> {
+        self.block_on(self.inner.blob_exists(blob_id))
+    }
+
+    /// Download a blob from the blob server.
+    ///
+    /// The returned data is still encrypted. If the blob cannot be found,
+    /// [`ApiError::BlobNotFound`] is returned. If it has expired,
+    /// [`ApiError::BlobExpired`] is returned.
+    ///
+    /// [`ApiError::BlobNotFound`]: errors/enum.ApiError.html#variant.BlobNotFound
+    /// [`ApiError::BlobExpired`]: errors/enum.ApiError.html#variant.BlobExpired
+    pub fn blob_download(&self, blob_id: &BlobId) -> Result<Vec<u8>, ApiError> {
+        self.block_on(self.inner.blob_download(blob_id))
+    }
+
+    /// Download and decrypt the media data of an incoming image, file, audio
+    /// or video message.
+    ///
+    /// The message must have been decrypted with
+    /// [`decrypt`](#method.decrypt) from the specified sender. Image messages
+    /// are decrypted with the key pair of sender and recipient, all other
+    /// media messages with the symmetric key contained in the message. Only
+    /// the main blob is fetched, thumbnails are ignored.
+    ///
+    /// If the message does not contain media, or if the data cannot be
+    /// decrypted, an [`ApiError::Other`](errors/enum.ApiError.html#variant.Other)
+    /// is returned. Download errors are returned like with
+    /// [`blob_download`](#method.blob_download).
+    pub fn fetch_media(
+        &self,
+        decrypted: &DecryptedMessage,
+        sender_key: &RecipientKey,
+    ) -> Result<Vec<u8>, ApiError> {
+        self.block_on(self.inner.fetch_media(decrypted, sender_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_chunks() {
+        let data = vec![7; 150 * 1024];
+        let chunks: Vec<Vec<u8>> = ReadChunks {
+            reader: &data[..],
+            done: false,
+        }
+        .collect::<io::Result<_>>()
+        .unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.concat(), data);
+    }
+
+    #[test]
+    fn test_eq() {
+        use crate::ApiBuilder;
+
+        let private_key = "998730fbcac1c57dbb181139de41d12835b3fae6af6acdf6ce91670262e88453";
+        let e2e = |secret: &str| {
+            ApiBuilder::new("*3MAGWID", secret)
+                .with_private_key_str(private_key)
+                .and_then(|builder| builder.into_e2e())
+                .unwrap()
+        };
+        assert_eq!(e2e("secret"), e2e("secret"));
+        assert_ne!(e2e("secret"), e2e("other"));
+
+        let simple = ApiBuilder::new("*3MAGWID", "secret").into_simple();
+        assert_eq!(simple, simple.clone());
+        assert_ne!(
+            simple,
+            ApiBuilder::new("*3MAGWID", "secret")
+                .with_custom_endpoint("http://localhost")
+                .into_simple()
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! Caching of looked up public keys.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::crypto::RecipientKey;
+
+/// A thread safe cache mapping Threema IDs to public keys.
+///
+/// Clones of the cache share the same entries.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PubkeyCache {
+    entries: Arc<Mutex<HashMap<String, (RecipientKey, Instant)>>>,
+    ttl: Option<Duration>,
+}
+
+impl PubkeyCache {
+    /// Create a new cache. If a TTL is specified, entries expire after that
+    /// duration.
+    pub(crate) fn new(ttl: Option<Duration>) -> Self {
+        PubkeyCache {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    /// Return the cached public key for the specified ID, if present and not
+    /// yet expired.
+    pub(crate) fn get(&self, id: &str) -> Option<RecipientKey> {
+        let mut entries = self.entries.lock().expect("Pubkey cache lock poisoned");
+        match (entries.get(id), self.ttl) {
+            (Some((_, inserted)), Some(ttl)) if inserted.elapsed() >= ttl => {
+                entries.remove(id);
+                None
+            }
+            (Some((key, _)), _) => Some(key.clone()),
+            (None, _) => None,
+        }
+    }
+
+    /// Store the public key for the specified ID.
+    pub(crate) fn insert(&self, id: &str, key: RecipientKey) {
+        self.entries
+            .lock()
+            .expect("Pubkey cache lock poisoned")
+            .insert(id.to_string(), (key, Instant::now()));
+    }
+
+    /// Remove all entries from the cache.
+    pub(crate) fn clear(&self) {
+        self.entries
+            .lock()
+            .expect("Pubkey cache lock poisoned")
+            .clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_get_insert() {
+        let cache = PubkeyCache::new(None);
+        assert!(cache.get("ECHOECHO").is_none());
+        cache.insert("ECHOECHO", [1; 32].into());
+        assert_eq!(cache.get("ECHOECHO").unwrap().as_bytes(), &[1; 32]);
+        assert!(cache.get("OTHERID1").is_none());
+    }
+
+    #[test]
+    fn test_shared_between_clones() {
+        let cache = PubkeyCache::new(None);
+        let clone = cache.clone();
+        clone.insert("ECHOECHO", [1; 32].into());
+        assert!(cache.get("ECHOECHO").is_some());
+        cache.clear();
+        assert!(clone.get("ECHOECHO").is_none());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let cache = PubkeyCache::new(Some(Duration::from_millis(10)));
+        cache.insert("ECHOECHO", [1; 32].into());
+        assert!(cache.get("ECHOECHO").is_some());
+        thread::sleep(Duration::from_millis(20));
+        assert!(cache.get("ECHOECHO").is_none());
+    }
+}
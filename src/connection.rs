@@ -2,49 +2,301 @@
 
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::Read;
+use std::future::Future;
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
+use bitflags::bitflags;
 use data_encoding::HEXLOWER;
+use mime::Mime;
+use reqwest::header::{EXPIRES, RETRY_AFTER};
 use reqwest::multipart;
-use reqwest::{Client, StatusCode};
+use reqwest::{Body, Client, Response, StatusCode, Url};
 
 use crate::errors::ApiError;
-use crate::types::BlobId;
+use crate::types::{BlobId, BlobUploadResult, MessageId};
 
-/// Map HTTP response status code to an ApiError if it isn't "200".
+/// Map a HTTP response status code other than "200" to an ApiError.
 ///
-/// Optionally, you can pass in the meaning of a 400 response code.
-pub(crate) fn map_response_code(
+/// Optionally, you can pass in the meaning of a 400 response code. The
+/// response body is attached to errors that don't have a fixed meaning.
+fn map_response_code(
     status: StatusCode,
     bad_request_meaning: Option<ApiError>,
-) -> Result<(), ApiError> {
+    body: &str,
+) -> ApiError {
+    let bad_status = || ApiError::HttpStatus {
+        code: status.as_u16(),
+        body: body.trim().to_string(),
+    };
     match status {
-        // 200
-        StatusCode::OK => Ok(()),
         // 400
-        StatusCode::BAD_REQUEST => match bad_request_meaning {
-            Some(error) => Err(error),
-            None => Err(ApiError::Other(format!(
-                "Bad response status code: {}",
-                StatusCode::BAD_REQUEST
-            ))),
-        },
+        StatusCode::BAD_REQUEST if is_mode_not_enabled(body) => ApiError::ModeNotEnabled,
+        StatusCode::BAD_REQUEST => bad_request_meaning.unwrap_or_else(bad_status),
         // 401
-        StatusCode::UNAUTHORIZED => Err(ApiError::BadCredentials),
+        StatusCode::UNAUTHORIZED => ApiError::BadCredentials,
         // 402
-        StatusCode::PAYMENT_REQUIRED => Err(ApiError::NoCredits),
+        StatusCode::PAYMENT_REQUIRED => ApiError::NoCredits,
         // 404
-        StatusCode::NOT_FOUND => Err(ApiError::IdNotFound),
+        StatusCode::NOT_FOUND => ApiError::IdNotFound,
+        // 410
+        StatusCode::GONE => ApiError::BlobExpired,
         // 413
-        StatusCode::PAYLOAD_TOO_LARGE => Err(ApiError::MessageTooLong),
+        StatusCode::PAYLOAD_TOO_LARGE => ApiError::MessageTooLong,
         // 500
-        StatusCode::INTERNAL_SERVER_ERROR => Err(ApiError::ServerError),
-        e => Err(ApiError::Other(format!("Bad response status code: {}", e))),
+        StatusCode::INTERNAL_SERVER_ERROR => ApiError::ServerError(body.trim().to_string()),
+        _ => bad_status(),
+    }
+}
+
+/// Return whether the body of a 400 response states that the account is not
+/// set up for the mode used (e.g. "account not set up for end-to-end mode").
+///
+/// The server does not always include a body, in that case the error cannot
+/// be distinguished from an invalid recipient.
+fn is_mode_not_enabled(body: &str) -> bool {
+    let body = body.to_lowercase();
+    body.contains("mode") && (body.contains("not set up") || body.contains("not enabled"))
+}
+
+/// The maximum number of characters of a parameter value that are logged.
+const MAX_LOGGED_VALUE_LENGTH: usize = 32;
+
+/// Redact a request parameter value for logging.
+///
+/// The API secret is replaced by `***`, long values (like ciphertexts) are
+/// truncated.
+fn redact_value<'a>(key: &str, value: &'a str) -> Cow<'a, str> {
+    if key == "secret" {
+        return Cow::Borrowed("***");
+    }
+    match value.char_indices().nth(MAX_LOGGED_VALUE_LENGTH) {
+        Some((end, _)) => Cow::Owned(format!("{}... ({} bytes)", &value[..end], value.len())),
+        None => Cow::Borrowed(value),
+    }
+}
+
+/// Redact the query parameters of a URL for logging.
+pub(crate) fn redact_url(url: &str) -> String {
+    let mut url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return "<invalid URL>".into(),
+    };
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| {
+            let v = redact_value(&k, &v).into_owned();
+            (k.into_owned(), v)
+        })
+        .collect();
+    if !pairs.is_empty() {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+/// Redact form parameters for logging. The parameters are sorted by key.
+fn redact_params<K: AsRef<str>, V: AsRef<str>>(params: &HashMap<K, V>) -> String {
+    let mut pairs: Vec<String> = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k.as_ref(), redact_value(k.as_ref(), v.as_ref())))
+        .collect();
+    pairs.sort();
+    pairs.join(", ")
+}
+
+/// The delay used for rate limited requests without a valid `Retry-After`
+/// header.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Redact an ID, phone number or e-mail address for tracing spans. Only
+/// the first two characters are kept.
+#[cfg(feature = "tracing")]
+pub(crate) fn redact(value: &str) -> String {
+    let prefix: String = value.chars().take(2).collect();
+    format!("{}***", prefix)
+}
+
+/// Return the delay specified by the `Retry-After` header of a response.
+///
+/// Only the delay-seconds format is supported, HTTP dates are ignored.
+fn parse_retry_after(res: &Response) -> Option<Duration> {
+    res.headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Return the point in time specified by the `Expires` header of a response.
+fn parse_expires(res: &Response) -> Option<SystemTime> {
+    let value = res.headers().get(EXPIRES)?.to_str().ok()?;
+    httpdate::parse_http_date(value.trim()).ok()
+}
+
+/// Return the response if its status code is "200". Otherwise, read the
+/// response body and map the status code to an ApiError.
+pub(crate) async fn check_response(
+    res: Response,
+    bad_request_meaning: Option<ApiError>,
+) -> Result<Response, ApiError> {
+    let status = res.status();
+    trace!(
+        "Response status for {}: {}",
+        redact_url(res.url().as_str()),
+        status
+    );
+    if status == StatusCode::OK {
+        return Ok(res);
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = parse_retry_after(&res).unwrap_or(DEFAULT_RETRY_AFTER);
+        return Err(ApiError::RateLimited { retry_after });
+    }
+    let body = res.text().await.unwrap_or_default();
+    if !body.trim().is_empty() {
+        debug!("Error response body ({}): {}", status, body.trim());
+    }
+    Err(map_response_code(status, bad_request_meaning, &body))
+}
+
+/// The maximum length of a text message in bytes.
+pub const MAX_TEXT_LENGTH: usize = 3500;
+
+/// Split a text into chunks that fit into a single text message.
+///
+/// Every chunk is at most [`MAX_TEXT_LENGTH`](constant.MAX_TEXT_LENGTH.html)
+/// bytes long. If possible, the text is split after a line break or
+/// whitespace, otherwise at a character boundary. Concatenating the chunks
+/// yields the original text.
+pub fn split_text(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > MAX_TEXT_LENGTH {
+        let mut boundary = MAX_TEXT_LENGTH;
+        while !rest.is_char_boundary(boundary) {
+            boundary -= 1;
+        }
+        let window = &rest[..boundary];
+        let split_at = window
+            .rfind('\n')
+            .or_else(|| window.rfind(char::is_whitespace))
+            .map(|pos| pos + window[pos..].chars().next().map_or(1, char::len_utf8))
+            .unwrap_or(boundary);
+        let (chunk, remainder) = rest.split_at(split_at);
+        chunks.push(chunk.to_string());
+        rest = remainder;
+    }
+    if !rest.is_empty() || chunks.is_empty() {
+        chunks.push(rest.to_string());
+    }
+    chunks
+}
+
+/// Maximum delay between two attempts of a request.
+///
+/// Rate limited requests are not retried if the server requests a longer
+/// delay.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Configuration for retrying failed requests.
+///
+/// Only transient errors ([`ApiError::ServerError`], [`ApiError::Timeout`]
+/// and [`ApiError::RequestError`]) are retried. Timeouts are only retried if
+/// requested by the caller, since the request may already have been processed
+/// by the server. The delay between attempts starts at `base_delay` and
+/// doubles with every retry, up to `max_delay`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(0, Duration::ZERO)
+    }
+}
+
+impl RetryPolicy {
+    pub(crate) fn new(max_retries: u32, base_delay: Duration) -> Self {
+        RetryPolicy {
+            max_retries,
+            base_delay,
+            max_delay: MAX_RETRY_DELAY,
+        }
+    }
+
+    /// Return whether a request that failed with the specified error should
+    /// be retried.
+    fn is_transient(error: &ApiError, retry_timeouts: bool) -> bool {
+        match error {
+            ApiError::Timeout(_) => retry_timeouts,
+            ApiError::ServerError(_) | ApiError::RequestError(_) | ApiError::RateLimited { .. } => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Run the request produced by `request` until it succeeds, fails with a
+    /// non-transient error or the maximum number of retries is exhausted.
+    ///
+    /// Timeouts are only retried if `retry_timeouts` is set. This must not be
+    /// the case for requests that are not idempotent (e.g. sending a message).
+    pub(crate) async fn run<T, F, Fut>(
+        &self,
+        retry_timeouts: bool,
+        mut request: F,
+    ) -> Result<T, ApiError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let result = request().await;
+            let delay = match result {
+                Err(ref e)
+                    if attempt < self.max_retries && Self::is_transient(e, retry_timeouts) =>
+                {
+                    match *e {
+                        // Honor the delay requested by the server, unless it
+                        // is too long
+                        ApiError::RateLimited { retry_after } if retry_after > self.max_delay => {
+                            warn!("Request was rate limited for {:?}, giving up", retry_after);
+                            return result;
+                        }
+                        ApiError::RateLimited { retry_after } => retry_after,
+                        _ => self
+                            .base_delay
+                            .saturating_mul(2u32.saturating_pow(attempt))
+                            .min(self.max_delay),
+                    }
+                }
+                _ => return result,
+            };
+            attempt += 1;
+            if let Err(ref e) = result {
+                warn!(
+                    "Request failed ({}), retrying in {:?} (attempt {}/{})",
+                    e, delay, attempt, self.max_retries
+                );
+            }
+            tokio::time::sleep(delay).await;
+        }
     }
 }
 
 /// Different ways to specify a message recipient in basic mode.
+///
+/// The constructors [`id`](#method.id), [`phone`](#method.phone) and
+/// [`email`](#method.email) validate the recipient, the `new_*` constructors
+/// don't.
 #[derive(Debug)]
 pub enum Recipient<'a> {
     /// Recipient identity (8 characters)
@@ -53,6 +305,13 @@ pub enum Recipient<'a> {
     Phone(Cow<'a, str>),
     /// Recipient e-mail address
     Email(Cow<'a, str>),
+    /// Recipient specified by an arbitrary request parameter, which is sent
+    /// as is and never validated. This allows using recipient types that
+    /// are not (yet) supported by this library.
+    Raw {
+        param: Cow<'a, str>,
+        value: Cow<'a, str>,
+    },
 }
 
 impl<'a> Recipient<'a> {
@@ -67,21 +326,117 @@ impl<'a> Recipient<'a> {
     pub fn new_email<T: Into<Cow<'a, str>>>(email: T) -> Self {
         Recipient::Email(email.into())
     }
+
+    /// Create a validated ID recipient.
+    ///
+    /// See [`validate`](#method.validate) for the rules.
+    pub fn id<T: Into<Cow<'a, str>>>(id: T) -> Result<Self, ApiError> {
+        Self::validated(Recipient::new_id(id))
+    }
+
+    /// Create a validated phone number recipient.
+    ///
+    /// See [`validate`](#method.validate) for the rules.
+    pub fn phone<T: Into<Cow<'a, str>>>(phone: T) -> Result<Self, ApiError> {
+        Self::validated(Recipient::new_phone(phone))
+    }
+
+    /// Create a validated e-mail recipient.
+    ///
+    /// See [`validate`](#method.validate) for the rules.
+    pub fn email<T: Into<Cow<'a, str>>>(email: T) -> Result<Self, ApiError> {
+        Self::validated(Recipient::new_email(email))
+    }
+
+    /// Create a recipient that is sent as the specified request parameter,
+    /// without any validation.
+    pub fn raw<P, V>(param: P, value: V) -> Self
+    where
+        P: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        Recipient::Raw {
+            param: param.into(),
+            value: value.into(),
+        }
+    }
+
+    fn validated(recipient: Self) -> Result<Self, ApiError> {
+        recipient.validate()?;
+        Ok(recipient)
+    }
+
+    /// Check whether the recipient is well-formed.
+    ///
+    /// An ID must consist of 8 alphanumeric characters (or `*`), a phone
+    /// number of 7 to 15 digits (E.164, without leading `+`) and an e-mail
+    /// address must contain a local part and a domain. Raw recipients are
+    /// always accepted. If the check fails,
+    /// [`ApiError::BadSenderOrRecipient`] is returned.
+    ///
+    /// [`ApiError::BadSenderOrRecipient`]: errors/enum.ApiError.html#variant.BadSenderOrRecipient
+    pub fn validate(&self) -> Result<(), ApiError> {
+        let valid = match *self {
+            Recipient::Id(ref id) => is_valid_id(id),
+            Recipient::Phone(ref phone) => {
+                (7..=15).contains(&phone.len()) && phone.chars().all(|c| c.is_ascii_digit())
+            }
+            Recipient::Email(ref email) => match email.split_once('@') {
+                Some((local, domain)) => {
+                    !local.is_empty()
+                        && domain.contains('.')
+                        && !domain.starts_with('.')
+                        && !domain.ends_with('.')
+                        && !domain.contains('@')
+                        && !email.chars().any(char::is_whitespace)
+                }
+                None => false,
+            },
+            Recipient::Raw { .. } => true,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(ApiError::BadSenderOrRecipient)
+        }
+    }
+}
+
+/// Return the redacted recipient for tracing spans.
+#[cfg(feature = "tracing")]
+fn redact_recipient(to: &Recipient<'_>) -> String {
+    match *to {
+        Recipient::Id(ref val)
+        | Recipient::Phone(ref val)
+        | Recipient::Email(ref val)
+        | Recipient::Raw { value: ref val, .. } => redact(val),
+    }
+}
+
+/// Return whether the specified string is a well-formed Threema ID.
+pub(crate) fn is_valid_id(id: &str) -> bool {
+    id.len() == 8 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '*')
 }
 
 /// Send a message to the specified recipient in basic mode.
-pub(crate) fn send_simple(
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "send_simple", skip_all, fields(to = %redact_recipient(to)))
+)]
+pub(crate) async fn send_simple(
+    client: &Client,
     endpoint: &str,
     from: &str,
-    to: &Recipient,
+    to: &Recipient<'_>,
     secret: &str,
     text: &str,
-) -> Result<String, ApiError> {
+) -> Result<MessageId, ApiError> {
     // Check text length (max 3500 bytes)
     // Note: Strings in Rust are UTF8, so len() returns the byte count.
-    if text.len() > 3500 {
+    if text.len() > MAX_TEXT_LENGTH {
         return Err(ApiError::MessageTooLong);
     }
+    to.validate()?;
 
     // Prepare POST data
     let mut params = HashMap::new();
@@ -92,72 +447,277 @@ pub(crate) fn send_simple(
         Recipient::Id(ref id) => params.insert("to", id),
         Recipient::Phone(ref phone) => params.insert("phone", phone),
         Recipient::Email(ref email) => params.insert("email", email),
+        Recipient::Raw {
+            ref param,
+            ref value,
+        } => params.insert(param, value),
     };
 
     // Send request
-    let mut res = Client::new()
-        .post(&format!("{}/send_simple", endpoint))
+    let url = format!("{}/send_simple", endpoint);
+    trace!("POST {} [{}]", url, redact_params(&params));
+    let res = client
+        .post(url)
         .form(&params)
         .header("accept", "application/json")
-        .send()?;
-    map_response_code(res.status(), Some(ApiError::BadSenderOrRecipient))?;
+        .send()
+        .await?;
+    let res = check_response(res, Some(ApiError::BadSenderOrRecipient)).await?;
 
-    // Read and return response body
-    let mut body = String::new();
-    res.read_to_string(&mut body)?;
+    // Read response body containing message ID
+    let body = res.text().await?;
+
+    MessageId::from_str(body.trim())
+}
+
+bitflags! {
+    /// The flags of a Threema message.
+    ///
+    /// The values correspond to the message flags of the Threema protocol.
+    /// The Gateway API only allows controlling [`PUSH`](#associatedconstant.PUSH)
+    /// and [`NO_DELIVERY_RECEIPTS`](#associatedconstant.NO_DELIVERY_RECEIPTS),
+    /// the other flags are managed by the Gateway server. See
+    /// [`SendOptions::from_flags`](struct.SendOptions.html#method.from_flags).
+    ///
+    /// The default flags contain only `PUSH`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct MessageFlags: u8 {
+        /// The recipient receives a push notification.
+        const PUSH = 0x01;
+        /// The message is not queued on the server if the recipient is not
+        /// connected.
+        ///
+        /// The Gateway API has no parameter for this flag, all messages are
+        /// queued until the recipient fetches them.
+        const NO_QUEUING = 0x02;
+        /// The message is not acknowledged by the server.
+        const NO_ACK = 0x04;
+        /// The message is a group message.
+        const GROUP = 0x10;
+        /// The message is only queued on the server for a short time.
+        ///
+        /// Like `NO_QUEUING`, this flag cannot be set through the Gateway API.
+        const SHORT_LIVED = 0x20;
+        /// The recipient does not send delivery receipts.
+        const NO_DELIVERY_RECEIPTS = 0x80;
+    }
+}
+
+impl MessageFlags {
+    /// The flags that can be set through the Gateway API.
+    const GATEWAY_SUPPORTED: MessageFlags =
+        MessageFlags::PUSH.union(MessageFlags::NO_DELIVERY_RECEIPTS);
+}
+
+impl Default for MessageFlags {
+    fn default() -> Self {
+        MessageFlags::PUSH
+    }
+}
+
+/// Options for sending an E2E message.
+///
+/// By default, the recipient sends delivery receipts and receives a push
+/// notification.
+///
+/// Note that the public nickname shown to recipients cannot be set per
+/// message. It is configured for the Gateway ID in the Threema Gateway
+/// administration.
+///
+/// ```
+/// use threema_gateway::SendOptions;
+///
+/// let options = SendOptions::new().delivery_receipts(false).push(false);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendOptions {
+    delivery_receipts: bool,
+    push: bool,
+    extra_params: HashMap<String, String>,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        SendOptions {
+            delivery_receipts: true,
+            push: true,
+            extra_params: HashMap::new(),
+        }
+    }
+}
+
+impl SendOptions {
+    /// Create the default send options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the recipient's device should send delivery receipts.
+    ///
+    /// Disabling delivery receipts can be useful for one-way communication
+    /// where the delivery receipts would be discarded anyway.
+    pub fn delivery_receipts(mut self, delivery_receipts: bool) -> Self {
+        self.delivery_receipts = delivery_receipts;
+        self
+    }
+
+    /// Set whether the recipient should receive a push notification.
+    pub fn push(mut self, push: bool) -> Self {
+        self.push = push;
+        self
+    }
 
-    Ok(body)
+    /// Create send options from message flags.
+    ///
+    /// Push notifications are enabled if `PUSH` is set, and delivery
+    /// receipts are disabled if `NO_DELIVERY_RECEIPTS` is set. If any flag
+    /// is set that cannot be controlled through the Gateway API, an
+    /// [`ApiError::Other`](errors/enum.ApiError.html#variant.Other) is
+    /// returned.
+    pub fn from_flags(flags: MessageFlags) -> Result<Self, ApiError> {
+        let unsupported = flags.difference(MessageFlags::GATEWAY_SUPPORTED);
+        if !unsupported.is_empty() {
+            return Err(ApiError::Other(format!(
+                "Message flags not supported by the Gateway API: {:?}",
+                unsupported
+            )));
+        }
+        Ok(SendOptions::new()
+            .push(flags.contains(MessageFlags::PUSH))
+            .delivery_receipts(!flags.contains(MessageFlags::NO_DELIVERY_RECEIPTS)))
+    }
+
+    /// Return the message flags corresponding to these options.
+    pub fn flags(&self) -> MessageFlags {
+        let mut flags = MessageFlags::empty();
+        flags.set(MessageFlags::PUSH, self.push);
+        flags.set(MessageFlags::NO_DELIVERY_RECEIPTS, !self.delivery_receipts);
+        flags
+    }
+
+    /// Add an additional POST parameter that is sent with the request.
+    ///
+    /// This is intended for API parameters that are not (yet) supported by
+    /// this library. Parameters set by the library itself cannot be
+    /// overridden.
+    pub fn extra_param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.extra_params.insert(key.into(), value.into());
+        self
+    }
 }
 
 /// Send an encrypted E2E message to the specified recipient.
-pub(crate) fn send_e2e(
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "send_e2e", skip_all, fields(to = %redact(to)))
+)]
+pub(crate) async fn send_e2e(
+    client: &Client,
     endpoint: &str,
     from: &str,
     to: &str,
     secret: &str,
     nonce: &[u8],
     ciphertext: &[u8],
-    delivery_receipts: bool,
-    additional_params: Option<HashMap<String, String>>,
-) -> Result<String, ApiError> {
+    options: &SendOptions,
+) -> Result<MessageId, ApiError> {
+    if !is_valid_id(to) {
+        return Err(ApiError::BadSenderOrRecipient);
+    }
+
     // Prepare POST data
-    let mut params = match additional_params {
-        Some(p) => p,
-        None => HashMap::new(),
-    };
+    let mut params = options.extra_params.clone();
     params.insert("from".into(), from.into());
     params.insert("to".into(), to.into());
     params.insert("secret".into(), secret.into());
     params.insert("nonce".into(), HEXLOWER.encode(nonce));
     params.insert("box".into(), HEXLOWER.encode(ciphertext));
-    if !delivery_receipts {
+    if !options.delivery_receipts {
         params.insert("noDeliveryReceipts".into(), "1".into());
     }
+    if !options.push {
+        params.insert("noPush".into(), "1".into());
+    }
 
     // Send request
-    let mut res = Client::new()
-        .post(&format!("{}/send_e2e", endpoint))
+    let url = format!("{}/send_e2e", endpoint);
+    trace!("POST {} [{}]", url, redact_params(&params));
+    let res = client
+        .post(url)
         .form(&params)
         .header("accept", "application/json")
-        .send()?;
-    map_response_code(res.status(), Some(ApiError::BadSenderOrRecipient))?;
+        .send()
+        .await?;
+    let res = check_response(res, Some(ApiError::BadSenderOrRecipient)).await?;
 
-    // Read and return response body
-    let mut body = String::new();
-    res.read_to_string(&mut body)?;
+    // Read response body containing message ID
+    let body = res.text().await?;
 
-    Ok(body)
+    MessageId::from_str(body.trim())
 }
 
 /// Upload a blob to the blob server.
-pub(crate) fn blob_upload(
+///
+/// If no media type is specified, `application/octet-stream` is used.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn blob_upload(
+    client: &Client,
     endpoint: &str,
     from: &str,
     secret: &str,
     data: &[u8],
     persist: bool,
+    media_type: Option<&Mime>,
+    additional_params: Option<HashMap<String, String>>,
+) -> Result<BlobUploadResult, ApiError> {
+    let part = multipart::Part::bytes(data.to_vec());
+    blob_upload_part(
+        client,
+        endpoint,
+        from,
+        secret,
+        part,
+        persist,
+        media_type,
+        additional_params,
+    )
+    .await
+}
+
+/// Upload a blob to the blob server, streaming the data from the specified
+/// body without buffering it in memory.
+///
+/// The length of the data in bytes must be known in advance.
+pub(crate) async fn blob_upload_stream(
+    client: &Client,
+    endpoint: &str,
+    from: &str,
+    secret: &str,
+    body: Body,
+    len: u64,
+    persist: bool,
+) -> Result<BlobUploadResult, ApiError> {
+    let part = multipart::Part::stream_with_length(body, len);
+    blob_upload_part(client, endpoint, from, secret, part, persist, None, None).await
+}
+
+/// Upload the specified multipart part to the blob server.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "blob_upload", skip_all)
+)]
+#[allow(clippy::too_many_arguments)]
+async fn blob_upload_part(
+    client: &Client,
+    endpoint: &str,
+    from: &str,
+    secret: &str,
+    part: multipart::Part,
+    persist: bool,
+    media_type: Option<&Mime>,
     additional_params: Option<HashMap<String, String>>,
-) -> Result<BlobId, ApiError> {
+) -> Result<BlobUploadResult, ApiError> {
     // Build URL
     let mut url = format!("{}/upload_blob?from={}&secret={}", endpoint, from, secret);
     if persist {
@@ -168,8 +728,7 @@ pub(crate) fn blob_upload(
     let mut form = multipart::Form::new();
     form = form.part(
         "blob",
-        multipart::Part::bytes(data.to_vec())
-            .mime_str("application/octet-stream")
+        part.mime_str(media_type.map_or("application/octet-stream", Mime::as_ref))
             .expect("Could not parse MIME string"),
     );
     if let Some(params) = additional_params {
@@ -179,56 +738,637 @@ pub(crate) fn blob_upload(
     }
 
     // Send request
-    let mut res = Client::new()
+    trace!("POST {}", redact_url(&url));
+    let res = client
         .post(&url)
         .multipart(form)
         .header("accept", "text/plain")
-        .send()?;
-    map_response_code(res.status(), Some(ApiError::BadBlob))?;
+        .send()
+        .await?;
+    let res = check_response(res, Some(ApiError::BadBlob)).await?;
+    let expires_at = parse_expires(&res);
 
     // Read response body containing blob ID
-    let mut body = String::new();
-    res.read_to_string(&mut body)?;
+    let body = res.text().await?;
 
-    BlobId::from_str(body.trim())
+    Ok(BlobUploadResult {
+        id: BlobId::from_str(body.trim())?,
+        expires_at,
+    })
+}
+
+/// Download a blob from the blob server.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "blob_download", skip_all, fields(blob_id = %blob_id))
+)]
+pub(crate) async fn blob_download(
+    client: &Client,
+    endpoint: &str,
+    from: &str,
+    secret: &str,
+    blob_id: &BlobId,
+) -> Result<Vec<u8>, ApiError> {
+    // Build URL
+    let url = format!(
+        "{}/blobs/{}?from={}&secret={}",
+        endpoint, blob_id, from, secret
+    );
+
+    debug!("Downloading blob {}", blob_id);
+    trace!("GET {}", redact_url(&url));
+
+    // Send request
+    let res = client.get(&url).send().await?;
+    let res = check_response(res, Some(ApiError::BadBlobId))
+        .await
+        .map_err(|e| match e {
+            ApiError::IdNotFound => ApiError::BlobNotFound,
+            e => e,
+        })?;
+
+    // Read and return response body
+    Ok(res.bytes().await?.to_vec())
+}
+
+/// Check whether a blob exists on the blob server, without downloading it.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "blob_exists", skip_all, fields(blob_id = %blob_id))
+)]
+pub(crate) async fn blob_exists(
+    client: &Client,
+    endpoint: &str,
+    from: &str,
+    secret: &str,
+    blob_id: &BlobId,
+) -> Result<bool, ApiError> {
+    // Build URL
+    let url = format!(
+        "{}/blobs/{}?from={}&secret={}",
+        endpoint, blob_id, from, secret
+    );
+
+    debug!("Checking whether blob {} exists", blob_id);
+    trace!("HEAD {}", redact_url(&url));
+
+    // Send request
+    let res = client.head(&url).send().await?;
+    match check_response(res, Some(ApiError::BadBlobId)).await {
+        Ok(_) => Ok(true),
+        Err(ApiError::IdNotFound) | Err(ApiError::BlobExpired) => Ok(false),
+        Err(e) => Err(e),
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+
     use super::*;
     use crate::errors::ApiError;
     use crate::MSGAPI_URL;
-    use std::iter::repeat;
 
-    #[test]
-    fn test_simple_max_length_ok() {
-        let text: String = repeat("à").take(3500 / 2).collect();
+    #[tokio::test]
+    async fn test_simple_max_length_ok() {
+        let text: String = "à".repeat(3500 / 2);
         let result = send_simple(
+            &Client::new(),
             MSGAPI_URL,
             "TESTTEST",
             &Recipient::new_id("ECHOECHO"),
             "secret",
             &text,
-        );
+        )
+        .await;
         if let Err(ApiError::MessageTooLong) = result {
             panic!()
         }
     }
 
-    #[test]
-    fn test_simple_max_length_too_long() {
-        let mut text: String = repeat("à").take(3500 / 2).collect();
+    #[tokio::test]
+    async fn test_simple_max_length_too_long() {
+        let mut text: String = "à".repeat(3500 / 2);
         text.push('x');
         let result = send_simple(
+            &Client::new(),
             MSGAPI_URL,
             "TESTTEST",
             &Recipient::new_id("ECHOECHO"),
             "secret",
             &text,
-        );
+        )
+        .await;
         match result {
             Err(ApiError::MessageTooLong) => (),
             _ => panic!(),
         }
     }
+
+    #[tokio::test]
+    async fn test_retry_transient_errors() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let attempts = Cell::new(0);
+        let result = policy
+            .run(true, || {
+                attempts.set(attempts.get() + 1);
+                let attempt = attempts.get();
+                async move {
+                    if attempt < 3 {
+                        Err(ApiError::ServerError(String::new()))
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up() {
+        let policy = RetryPolicy::new(2, Duration::from_millis(1));
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = policy
+            .run(true, || {
+                attempts.set(attempts.get() + 1);
+                async { Err(ApiError::ServerError(String::new())) }
+            })
+            .await;
+        assert!(matches!(result, Err(ApiError::ServerError(_))));
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_on_permanent_errors() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = policy
+            .run(true, || {
+                attempts.set(attempts.get() + 1);
+                async { Err(ApiError::BadCredentials) }
+            })
+            .await;
+        assert!(matches!(result, Err(ApiError::BadCredentials)));
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_by_default() {
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = RetryPolicy::default()
+            .run(true, || {
+                attempts.set(attempts.get() + 1);
+                async { Err(ApiError::ServerError(String::new())) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn test_map_response_code_attaches_body() {
+        match map_response_code(StatusCode::BAD_REQUEST, None, "recipient blocked\n") {
+            ApiError::HttpStatus { code, body } => {
+                assert_eq!(code, 400);
+                assert_eq!(body, "recipient blocked");
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+        match map_response_code(StatusCode::INTERNAL_SERVER_ERROR, None, "database down") {
+            ApiError::ServerError(body) => assert_eq!(body, "database down"),
+            other => panic!("Unexpected error: {:?}", other),
+        }
+        match map_response_code(StatusCode::IM_A_TEAPOT, None, "") {
+            ApiError::HttpStatus { code, body } => {
+                assert_eq!(code, 418);
+                assert!(body.is_empty());
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_map_response_code_bad_request_meaning() {
+        let error = map_response_code(
+            StatusCode::BAD_REQUEST,
+            Some(ApiError::BadBlobId),
+            "invalid blob id",
+        );
+        assert!(matches!(error, ApiError::BadBlobId));
+    }
+
+    #[test]
+    fn test_map_response_code_mode_not_enabled() {
+        let error = map_response_code(
+            StatusCode::BAD_REQUEST,
+            Some(ApiError::BadSenderOrRecipient),
+            "Account not set up for end-to-end mode",
+        );
+        assert!(matches!(error, ApiError::ModeNotEnabled));
+        let error = map_response_code(
+            StatusCode::BAD_REQUEST,
+            Some(ApiError::BadSenderOrRecipient),
+            "",
+        );
+        assert!(matches!(error, ApiError::BadSenderOrRecipient));
+    }
+
+    #[test]
+    fn test_recipient_validate() {
+        assert!(Recipient::new_id("ECHOECHO").validate().is_ok());
+        assert!(Recipient::new_id("*3MAGWID").validate().is_ok());
+        assert!(Recipient::new_id("ECHO").validate().is_err());
+        assert!(Recipient::new_id("ECHO ECH").validate().is_err());
+        assert!(Recipient::new_phone("41791234567").validate().is_ok());
+        assert!(Recipient::new_phone("+41791234567").validate().is_err());
+        assert!(Recipient::new_phone("123").validate().is_err());
+        assert!(Recipient::new_email("user@example.com").validate().is_ok());
+        assert!(Recipient::new_email("user@localhost").validate().is_err());
+        assert!(Recipient::new_email("@example.com").validate().is_err());
+        assert!(Recipient::new_email("user@@example.com")
+            .validate()
+            .is_err());
+        assert!(Recipient::new_email("us er@example.com")
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_recipient_constructors() {
+        assert!(matches!(Recipient::id("ECHOECHO"), Ok(Recipient::Id(_))));
+        assert!(matches!(
+            Recipient::id("ECHO"),
+            Err(ApiError::BadSenderOrRecipient)
+        ));
+        assert!(matches!(
+            Recipient::phone("41791234567"),
+            Ok(Recipient::Phone(_))
+        ));
+        assert!(Recipient::phone("+41791234567").is_err());
+        assert!(matches!(
+            Recipient::email("user@example.com"),
+            Ok(Recipient::Email(_))
+        ));
+        assert!(Recipient::email("user").is_err());
+        assert!(Recipient::raw("phone", "+41 79").validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_simple_invalid_recipient() {
+        let result = send_simple(
+            &Client::new(),
+            MSGAPI_URL,
+            "TESTTEST",
+            &Recipient::new_phone("+41 79 123 45 67"),
+            "secret",
+            "Hello",
+        )
+        .await;
+        assert!(matches!(result, Err(ApiError::BadSenderOrRecipient)));
+    }
+
+    /// Serve a single HTTP request on a local port. Return the endpoint URL
+    /// and a handle resolving to the raw request.
+    async fn serve_once(
+        status: &'static str,
+        response_body: &'static str,
+    ) -> (String, tokio::task::JoinHandle<Vec<u8>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length: "))
+                        .map(|len| len.trim().parse::<usize>().unwrap())
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {}\r\ncontent-length: {}\r\n\r\n{}",
+                status,
+                response_body.len(),
+                response_body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+        (endpoint, handle)
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let _handle = tokio::spawn(async move {
+            // Accept the connection, but never respond
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+        let client = Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let result =
+            blob_download(&client, &endpoint, "*3MAGWID", "secret", &BlobId([0; 16])).await;
+        assert!(matches!(result, Err(ApiError::Timeout(_))));
+
+        // Timeouts are only retried on request
+        let policy = RetryPolicy::new(2, Duration::from_millis(1));
+        for (retry_timeouts, expected_attempts) in [(false, 1), (true, 3)] {
+            let attempts = Cell::new(0);
+            let result = policy
+                .run(retry_timeouts, || {
+                    attempts.set(attempts.get() + 1);
+                    blob_download(&client, &endpoint, "*3MAGWID", "secret", &BlobId([0; 16]))
+                })
+                .await;
+            assert!(matches!(result, Err(ApiError::Timeout(_))));
+            assert_eq!(attempts.get(), expected_attempts);
+        }
+
+        // Other errors are still request errors
+        let result = blob_download(
+            &client,
+            "http://127.0.0.1:1",
+            "*3MAGWID",
+            "secret",
+            &BlobId([0; 16]),
+        )
+        .await;
+        assert!(matches!(result, Err(ApiError::RequestError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited() {
+        let (endpoint, _) = serve_once("429 Too Many Requests\r\nretry-after: 7", "").await;
+        let res = Client::new().get(&endpoint).send().await.unwrap();
+        match check_response(res, None).await {
+            Err(ApiError::RateLimited { retry_after }) => {
+                assert_eq!(retry_after, Duration::from_secs(7))
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let (endpoint, _) = serve_once("429 Too Many Requests", "").await;
+        let res = Client::new().get(&endpoint).send().await.unwrap();
+        match check_response(res, None).await {
+            Err(ApiError::RateLimited { retry_after }) => {
+                assert_eq!(retry_after, DEFAULT_RETRY_AFTER)
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_rate_limited() {
+        let policy = RetryPolicy::new(1, Duration::from_secs(60));
+        let attempts = Cell::new(0);
+        let result = policy
+            .run(true, || {
+                attempts.set(attempts.get() + 1);
+                let attempt = attempts.get();
+                async move {
+                    if attempt < 2 {
+                        Err(ApiError::RateLimited {
+                            retry_after: Duration::from_millis(1),
+                        })
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_if_rate_limited_too_long() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = policy
+            .run(true, || {
+                attempts.set(attempts.get() + 1);
+                async {
+                    Err(ApiError::RateLimited {
+                        retry_after: MAX_RETRY_DELAY + Duration::from_secs(1),
+                    })
+                }
+            })
+            .await;
+        assert!(matches!(result, Err(ApiError::RateLimited { .. })));
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_e2e_options() {
+        let (endpoint, request) = serve_once("200 OK", "0123456789abcdef").await;
+        let options = SendOptions::new()
+            .delivery_receipts(false)
+            .push(false)
+            .extra_param("foo", "bar");
+        let result = send_e2e(
+            &Client::new(),
+            &endpoint,
+            "*3MAGWID",
+            "ECHOECHO",
+            "secret",
+            &[1; 24],
+            &[2; 32],
+            &options,
+        )
+        .await;
+        assert_eq!(result.unwrap().as_hex(), "0123456789abcdef");
+        let request = request.await.unwrap();
+        let request_text = String::from_utf8_lossy(&request);
+        let body = request_text.split("\r\n\r\n").nth(1).unwrap();
+        let params: HashMap<String, String> = form_urlencoded::parse(body.as_bytes())
+            .into_owned()
+            .collect();
+        assert_eq!(params["noDeliveryReceipts"], "1");
+        assert_eq!(params["noPush"], "1");
+        assert_eq!(params["foo"], "bar");
+        assert_eq!(params["to"], "ECHOECHO");
+    }
+
+    #[test]
+    fn test_send_options_default() {
+        let options = SendOptions::default();
+        assert!(options.delivery_receipts);
+        assert!(options.push);
+        assert!(options.extra_params.is_empty());
+    }
+
+    #[test]
+    fn test_send_options_flags() {
+        assert_eq!(SendOptions::default().flags(), MessageFlags::default());
+        let options = SendOptions::from_flags(MessageFlags::NO_DELIVERY_RECEIPTS).unwrap();
+        assert_eq!(
+            options,
+            SendOptions::new().push(false).delivery_receipts(false)
+        );
+        assert_eq!(options.flags(), MessageFlags::NO_DELIVERY_RECEIPTS);
+        let options = SendOptions::from_flags(MessageFlags::PUSH).unwrap();
+        assert_eq!(options, SendOptions::new());
+        assert!(SendOptions::from_flags(MessageFlags::PUSH | MessageFlags::GROUP).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_blob_upload_stream() {
+        let blob_id = "0123456789abcdef0123456789abcdef";
+        let (endpoint, request) = serve_once("200 OK", blob_id).await;
+        let data = vec![0x42; 100_000];
+        let body = Body::from(data.clone());
+        let result = blob_upload_stream(
+            &Client::new(),
+            &endpoint,
+            "*3MAGWID",
+            "secret",
+            body,
+            data.len() as u64,
+            false,
+        )
+        .await;
+        assert_eq!(result.unwrap().id, BlobId::from_str(blob_id).unwrap());
+        let request = request.await.unwrap();
+        let request_text = String::from_utf8_lossy(&request);
+        assert!(request_text.starts_with("POST /upload_blob?from=*3MAGWID&secret=secret "));
+        assert!(request
+            .windows(data.len())
+            .any(|window| window == &data[..]));
+    }
+
+    #[test]
+    fn test_split_text_short() {
+        assert_eq!(split_text("Hello"), vec!["Hello"]);
+        assert_eq!(split_text(""), vec![""]);
+    }
+
+    #[test]
+    fn test_split_text_whitespace() {
+        let text = format!("{} {}", "a".repeat(3000), "b".repeat(1000));
+        let chunks = split_text(&text);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], format!("{} ", "a".repeat(3000)));
+        assert_eq!(chunks[1], "b".repeat(1000));
+    }
+
+    #[test]
+    fn test_split_text_utf8_boundaries() {
+        let text = "à".repeat(5000);
+        let chunks = split_text(&text);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= MAX_TEXT_LENGTH));
+        assert_eq!(chunks.concat(), text);
+    }
+
+    #[tokio::test]
+    async fn test_blob_download_errors() {
+        let blob_id = BlobId::new([0; 16]);
+        for (status, expected) in &[
+            ("404 Not Found", "BlobNotFound"),
+            ("410 Gone", "BlobExpired"),
+        ] {
+            let (endpoint, _) = serve_once(status, "").await;
+            let result =
+                blob_download(&Client::new(), &endpoint, "*3MAGWID", "secret", &blob_id).await;
+            assert_eq!(format!("{:?}", result.unwrap_err()), *expected);
+        }
+    }
+
+    #[test]
+    fn test_redact_url() {
+        assert_eq!(
+            redact_url("https://msgapi.threema.ch/pubkeys/ECHOECHO?from=*3MAGWID&secret=s3cr3t"),
+            "https://msgapi.threema.ch/pubkeys/ECHOECHO?from=*3MAGWID&secret=***"
+        );
+        assert_eq!(
+            redact_url("https://msgapi.threema.ch/credits"),
+            "https://msgapi.threema.ch/credits"
+        );
+        assert_eq!(redact_url("not a url"), "<invalid URL>");
+    }
+
+    #[test]
+    fn test_redact_params() {
+        let mut params = HashMap::new();
+        params.insert("to", "ECHOECHO".to_string());
+        params.insert("secret", "s3cr3t".to_string());
+        params.insert("box", "ab".repeat(40));
+        assert_eq!(
+            redact_params(&params),
+            format!(
+                "box={}... (80 bytes), secret=***, to=ECHOECHO",
+                "ab".repeat(16)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blob_upload_expires() {
+        let blob_id = "0123456789abcdef0123456789abcdef";
+        let (endpoint, _) =
+            serve_once("200 OK\r\nexpires: Sun, 06 Nov 1994 08:49:37 GMT", blob_id).await;
+        let result = blob_upload(
+            &Client::new(),
+            &endpoint,
+            "*3MAGWID",
+            "secret",
+            b"data",
+            false,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.id, BlobId::from_str(blob_id).unwrap());
+        assert_eq!(
+            result.expires_at,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(784111777))
+        );
+
+        let (endpoint, _) = serve_once("200 OK", blob_id).await;
+        let result = blob_upload(
+            &Client::new(),
+            &endpoint,
+            "*3MAGWID",
+            "secret",
+            b"data",
+            false,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.expires_at, None);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("ECHOECHO"), "EC***");
+        assert_eq!(redact("41791234567"), "41***");
+        assert_eq!(redact("ä"), "ä***");
+        assert_eq!(
+            redact_recipient(&Recipient::new_email("user@example.com")),
+            "us***"
+        );
+    }
 }
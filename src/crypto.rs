@@ -1,19 +1,26 @@
 //! Encrypt and decrypt messages.
 
-use std::convert::Into;
-use std::io::Write;
-use std::iter::repeat;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::iter::{once, repeat_n};
+use std::num::NonZeroU8;
 use std::str::FromStr;
 
-use byteorder::{LittleEndian, WriteBytesExt};
-use data_encoding::{HEXLOWER, HEXLOWER_PERMISSIVE};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use data_encoding::{BASE32_NOPAD, BASE64, BASE64_NOPAD, HEXLOWER, HEXLOWER_PERMISSIVE};
+use pbkdf2::pbkdf2_hmac;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use serde_json as json;
-use sodiumoxide::crypto::box_;
-use sodiumoxide::randombytes::randombytes_into;
+use sha2::{Digest, Sha256};
 
-use crate::errors::CryptoError;
-use crate::types::{BlobId, FileMessage, MessageType};
-use crate::{PublicKey, SecretKey};
+use crate::backend::{self, randombytes_into};
+use crate::connection::is_valid_id;
+use crate::errors::{ApiBuilderError, CryptoError};
+use crate::types::{
+    BallotMessage, BlobId, DeliveryReceipt, FileMessage, FsControlMessage, MessageType,
+};
+use crate::{Key, PrecomputedKey, PublicKey, SecretKey};
 
 /// Return a random number in the range `[1, 255]`.
 fn random_padding_amount() -> u8 {
@@ -27,14 +34,58 @@ fn random_padding_amount() -> u8 {
 }
 
 /// An encrypted message. Contains both the ciphertext and the nonce.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EncryptedMessage {
+    #[cfg_attr(feature = "serde", serde(with = "crate::types::serde_hex"))]
     pub ciphertext: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::types::serde_hex"))]
     pub nonce: [u8; 24],
+    /// The type of the encrypted message, if known. This is only used for
+    /// introspection (e.g. logging) and is not transmitted.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub msg_type: Option<MessageType>,
+}
+
+/// A decrypted message.
+#[derive(Debug, PartialEq)]
+pub enum DecryptedMessage {
+    /// A text message.
+    Text(String),
+    /// An image message. The image data must be downloaded from the blob
+    /// server and decrypted with the contained nonce.
+    Image {
+        blob_id: BlobId,
+        img_size_bytes: u32,
+        image_data_nonce: [u8; 24],
+    },
+    /// A delivery receipt.
+    DeliveryReceipt(DeliveryReceipt),
+    /// A message of a type that is not (yet) parsed by this library. The
+    /// data does not contain the message type byte and the padding.
+    Other { msgtype: u8, data: Vec<u8> },
 }
 
 /// The public key of a recipient.
+///
+/// Keys are compared in constant time.
+#[derive(Debug, Clone)]
 pub struct RecipientKey(pub PublicKey);
 
+impl PartialEq for RecipientKey {
+    fn eq(&self, other: &Self) -> bool {
+        backend::ct_eq(self.as_bytes(), other.as_bytes())
+    }
+}
+
+impl Eq for RecipientKey {}
+
+impl Hash for RecipientKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state);
+    }
+}
+
 impl From<PublicKey> for RecipientKey {
     /// Create a `RecipientKey` from a `PublicKey` instance.
     fn from(val: PublicKey) -> Self {
@@ -49,10 +100,10 @@ impl From<[u8; 32]> for RecipientKey {
     }
 }
 
-impl Into<String> for RecipientKey {
+impl From<RecipientKey> for String {
     /// Encode the key bytes as lowercase hex string.
-    fn into(self) -> String {
-        HEXLOWER.encode(&(self.0).0)
+    fn from(val: RecipientKey) -> Self {
+        HEXLOWER.encode(&(val.0).0)
     }
 }
 
@@ -69,13 +120,57 @@ impl RecipientKey {
     pub fn as_bytes(&self) -> &[u8] {
         &(self.0).0
     }
+
+    /// Create a `RecipientKey` from a base64 encoded string slice. The
+    /// trailing padding is optional.
+    pub fn from_base64(val: &str) -> Result<Self, CryptoError> {
+        let val = val.trim();
+        let encoding = if val.ends_with('=') {
+            &BASE64
+        } else {
+            &BASE64_NOPAD
+        };
+        let bytes = encoding.decode(val.as_bytes()).map_err(|e| {
+            CryptoError::BadKey(format!("Could not decode public key base64 string: {}", e))
+        })?;
+        RecipientKey::from_bytes(bytes.as_slice())
+    }
+
+    /// Parse the payload of a Threema ID QR code.
+    ///
+    /// The payload has the format `3mid:<ID>,<hex encoded public key>`.
+    /// Return the Threema ID and its public key.
+    pub fn from_qr_payload(payload: &str) -> Result<(String, Self), CryptoError> {
+        let invalid = |msg: &str| CryptoError::BadKey(format!("Invalid QR code payload: {}", msg));
+        let data = payload
+            .trim()
+            .strip_prefix("3mid:")
+            .ok_or_else(|| invalid("Missing \"3mid:\" prefix"))?;
+        let mut fields = data.split(',');
+        let id = fields.next().unwrap_or_default();
+        if !is_valid_id(id) {
+            return Err(invalid("Invalid Threema ID"));
+        }
+        let key = fields.next().ok_or_else(|| invalid("Missing public key"))?;
+        if key.len() != 64 {
+            return Err(invalid("Public key must be 64 hex characters"));
+        }
+        Ok((id.to_string(), key.parse()?))
+    }
 }
 
 impl FromStr for RecipientKey {
     type Err = CryptoError;
 
-    /// Create a `RecipientKey` from a hex encoded string slice.
+    /// Create a `RecipientKey` from a hex or base64 encoded string slice.
+    ///
+    /// A string of 64 hex characters is decoded as hex, everything else as
+    /// base64 (see [`from_base64`](#method.from_base64)).
     fn from_str(val: &str) -> Result<Self, Self::Err> {
+        let is_hex = val.len() == 64 && val.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_hex {
+            return RecipientKey::from_base64(val);
+        }
         let bytes = HEXLOWER_PERMISSIVE.decode(val.as_bytes()).map_err(|e| {
             CryptoError::BadKey(format!("Could not decode public key hex string: {}", e))
         })?;
@@ -83,21 +178,132 @@ impl FromStr for RecipientKey {
     }
 }
 
+/// Generate a new random key pair.
+///
+/// The private key can be passed to
+/// [`ApiBuilder::with_private_key`](struct.ApiBuilder.html#method.with_private_key),
+/// the public key must be registered with the Threema Gateway.
+pub fn generate_keypair() -> (PublicKey, SecretKey) {
+    backend::gen_keypair()
+}
+
+/// Generate a new random key pair and return the public and the private key
+/// as lowercase hex strings.
+pub fn generate_keypair_hex() -> (String, String) {
+    let (public_key, private_key) = generate_keypair();
+    (
+        HEXLOWER.encode(&public_key.0),
+        HEXLOWER.encode(&private_key.0),
+    )
+}
+
+/// Number of PBKDF2 iterations used to derive the key of a Threema ID backup.
+const ID_BACKUP_ITERATIONS: u32 = 100_000;
+
+/// Decrypt a Threema ID backup and return the Threema ID and its private key.
+///
+/// The backup is the Base32 encoding of an 8 byte salt followed by the
+/// encrypted data, in groups of four characters separated by dashes. The data
+/// is encrypted with XSalsa20 (zero nonce), using a key derived from the
+/// password with PBKDF2-HMAC-SHA256. It consists of the ID, the private key
+/// and the first two bytes of the SHA-256 hash of both, which is used to
+/// detect a wrong password.
+pub(crate) fn decrypt_id_backup(
+    backup: &str,
+    password: &str,
+) -> Result<(String, SecretKey), ApiBuilderError> {
+    let encoded: String = backup
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    let bytes = BASE32_NOPAD
+        .decode(encoded.as_bytes())
+        .map_err(|e| ApiBuilderError::InvalidBackup(format!("Could not decode backup: {}", e)))?;
+    if bytes.len() != 50 {
+        return Err(ApiBuilderError::InvalidBackup(format!(
+            "Backup must contain 50 bytes, not {}",
+            bytes.len()
+        )));
+    }
+    let (salt, encrypted) = bytes.split_at(8);
+
+    let mut key = [0; 32];
+    pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, ID_BACKUP_ITERATIONS, &mut key);
+    let decrypted = backend::stream_xor(encrypted, &[0; 24], &key);
+    let (data, hash) = decrypted.split_at(40);
+    if !backend::ct_eq(&Sha256::digest(data)[..2], hash) {
+        return Err(ApiBuilderError::WrongBackupPassword);
+    }
+
+    let (id, private_key) = data.split_at(8);
+    let id = String::from_utf8(id.to_vec())
+        .ok()
+        .filter(|id| is_valid_id(id))
+        .ok_or_else(|| ApiBuilderError::InvalidBackup("Backup contains an invalid ID".into()))?;
+    let private_key = SecretKey::from_slice(private_key).expect("Private key has 32 bytes");
+    Ok((id, private_key))
+}
+
 /// Encrypt data for the recipient.
 pub fn encrypt_raw(
     data: &[u8],
     public_key: &PublicKey,
     private_key: &SecretKey,
 ) -> EncryptedMessage {
-    sodiumoxide::init().expect("Could not initialize sodiumoxide library.");
-    let nonce = box_::gen_nonce();
-    let ciphertext = box_::seal(&data, &nonce, public_key, private_key);
+    let nonce = backend::gen_nonce();
+    encrypt_raw_with_nonce(data, &nonce, public_key, private_key)
+}
+
+/// Encrypt data for the recipient, using the specified nonce.
+///
+/// Like [`encrypt_raw`](fn.encrypt_raw.html), this neither adds the message
+/// type byte nor padding, see [`encrypt_with_nonce`](fn.encrypt_with_nonce.html)
+/// to encrypt a message. A nonce must never be reused with the same key pair.
+pub fn encrypt_raw_with_nonce(
+    data: &[u8],
+    nonce: &[u8; 24],
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let ciphertext = backend::seal(data, nonce, public_key, private_key);
     EncryptedMessage {
         ciphertext,
-        nonce: nonce.0,
+        nonce: *nonce,
+        msg_type: None,
     }
 }
 
+/// Prepend the message type byte and append the specified amount of PKCS#7
+/// style padding.
+fn pad(data: &[u8], msgtype: MessageType, padding_amount: u8) -> Vec<u8> {
+    let padding = repeat_n(padding_amount, padding_amount as usize);
+    once(msgtype.into())
+        .chain(data.iter().cloned())
+        .chain(padding)
+        .collect()
+}
+
+/// Validate and remove the PKCS#7 style padding.
+///
+/// Return the message type byte followed by the message data. The padding
+/// must be between 1 and 255 bytes long, and at least one byte (the message
+/// type) must remain.
+fn unpad(padded_plaintext: &[u8]) -> Result<&[u8], CryptoError> {
+    let padding_amount = match padded_plaintext.last() {
+        Some(amount) => *amount as usize,
+        None => return Err(CryptoError::EmptyMessage),
+    };
+    if padding_amount == 0 || padding_amount >= padded_plaintext.len() {
+        return Err(CryptoError::BadPadding);
+    }
+    let (plaintext, padding) = padded_plaintext.split_at(padded_plaintext.len() - padding_amount);
+    if !padding.iter().all(|b| *b as usize == padding_amount) {
+        return Err(CryptoError::BadPadding);
+    }
+    Ok(plaintext)
+}
+
 /// Encrypt a message for the recipient.
 pub fn encrypt(
     data: &[u8],
@@ -106,26 +312,76 @@ pub fn encrypt(
     private_key: &SecretKey,
 ) -> EncryptedMessage {
     // Add random amount of PKCS#7 style padding
-    let padding_amount = random_padding_amount();
-    let padding = repeat(padding_amount).take(padding_amount as usize);
-    let msgtype_byte = repeat(msgtype.into()).take(1);
-    let padded_plaintext: Vec<u8> = msgtype_byte
-        .chain(data.iter().cloned())
-        .chain(padding)
-        .collect();
+    let padded_plaintext = pad(data, msgtype, random_padding_amount());
 
     // Encrypt
-    encrypt_raw(&padded_plaintext, &public_key, &private_key)
+    EncryptedMessage {
+        msg_type: Some(msgtype),
+        ..encrypt_raw(&padded_plaintext, public_key, private_key)
+    }
 }
 
-/// Encrypt an image message for the recipient.
-pub fn encrypt_image_msg(
-    blob_id: &BlobId,
-    img_size_bytes: u32,
-    image_data_nonce: &[u8; 24],
+/// Precompute the shared key of the recipient's public key and the sender's
+/// private key.
+///
+/// Encrypting and decrypting with a precomputed key is faster when many
+/// messages are exchanged with the same recipient.
+pub fn precompute(public_key: &PublicKey, private_key: &SecretKey) -> PrecomputedKey {
+    backend::precompute(public_key, private_key)
+}
+
+/// Encrypt data for the recipient, using a precomputed key.
+pub fn encrypt_raw_precomputed(data: &[u8], key: &PrecomputedKey) -> EncryptedMessage {
+    let nonce = backend::gen_nonce();
+    EncryptedMessage {
+        ciphertext: backend::seal_precomputed(data, &nonce, key),
+        nonce,
+        msg_type: None,
+    }
+}
+
+/// Encrypt a message for the recipient, using a precomputed key.
+pub fn encrypt_precomputed(
+    data: &[u8],
+    msgtype: MessageType,
+    key: &PrecomputedKey,
+) -> EncryptedMessage {
+    let padded_plaintext = pad(data, msgtype, random_padding_amount());
+    EncryptedMessage {
+        msg_type: Some(msgtype),
+        ..encrypt_raw_precomputed(&padded_plaintext, key)
+    }
+}
+
+/// Encrypt a message for the recipient, using the specified nonce and amount
+/// of padding (in bytes).
+///
+/// The padding hides the length of the message, so the amount should be
+/// chosen randomly (and stored, if the message needs to be re-encrypted
+/// identically). A nonce must never be reused with the same key pair. Use
+/// [`encrypt`](fn.encrypt.html) to encrypt a message with a random nonce and
+/// padding.
+pub fn encrypt_with_nonce(
+    data: &[u8],
+    msgtype: MessageType,
+    nonce: &[u8; 24],
+    padding_amount: NonZeroU8,
     public_key: &PublicKey,
     private_key: &SecretKey,
 ) -> EncryptedMessage {
+    let padded_plaintext = pad(data, msgtype, padding_amount.get());
+    EncryptedMessage {
+        msg_type: Some(msgtype),
+        ..encrypt_raw_with_nonce(&padded_plaintext, nonce, public_key, private_key)
+    }
+}
+
+/// Return the payload of an image message.
+fn image_msg_payload(
+    blob_id: &BlobId,
+    img_size_bytes: u32,
+    image_data_nonce: &[u8; 24],
+) -> [u8; 44] {
     let mut data = [0; 44];
     // Since we're writing to an array and not to a file or socket, these
     // write operations should never fail.
@@ -138,10 +394,280 @@ pub fn encrypt_image_msg(
     (&mut data[20..44])
         .write_all(image_data_nonce)
         .expect("Writing to buffer failed");
+    data
+}
+
+/// Encrypt an image message for the recipient.
+pub fn encrypt_image_msg(
+    blob_id: &BlobId,
+    img_size_bytes: u32,
+    image_data_nonce: &[u8; 24],
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let data = image_msg_payload(blob_id, img_size_bytes, image_data_nonce);
     let msgtype = MessageType::Image;
     encrypt(&data, msgtype, public_key, private_key)
 }
 
+/// Encrypt an image message for the recipient, using the specified nonce
+/// and amount of padding for the message itself.
+///
+/// See [`encrypt_with_nonce`](fn.encrypt_with_nonce.html) for details.
+pub fn encrypt_image_msg_with_nonce(
+    blob_id: &BlobId,
+    img_size_bytes: u32,
+    image_data_nonce: &[u8; 24],
+    nonce: &[u8; 24],
+    padding_amount: NonZeroU8,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let data = image_msg_payload(blob_id, img_size_bytes, image_data_nonce);
+    let msgtype = MessageType::Image;
+    encrypt_with_nonce(
+        &data,
+        msgtype,
+        nonce,
+        padding_amount,
+        public_key,
+        private_key,
+    )
+}
+
+/// Encrypt an audio message for the recipient.
+pub fn encrypt_audio_msg(
+    blob_id: &BlobId,
+    duration_seconds: u16,
+    audio_size_bytes: u32,
+    blob_encryption_key: &Key,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let mut data = [0; 54];
+    // Since we're writing to an array and not to a file or socket, these
+    // write operations should never fail.
+    (&mut data[0..2])
+        .write_u16::<LittleEndian>(duration_seconds)
+        .expect("Writing to buffer failed");
+    (&mut data[2..18])
+        .write_all(&blob_id.0)
+        .expect("Writing to buffer failed");
+    (&mut data[18..22])
+        .write_u32::<LittleEndian>(audio_size_bytes)
+        .expect("Writing to buffer failed");
+    (&mut data[22..54])
+        .write_all(&blob_encryption_key.0)
+        .expect("Writing to buffer failed");
+    let msgtype = MessageType::Audio;
+    encrypt(&data, msgtype, public_key, private_key)
+}
+
+/// Encrypt a video message for the recipient.
+#[allow(clippy::too_many_arguments)]
+pub fn encrypt_video_msg(
+    blob_id: &BlobId,
+    thumbnail_blob_id: &BlobId,
+    duration_seconds: u16,
+    video_size_bytes: u32,
+    thumbnail_size_bytes: u32,
+    blob_encryption_key: &Key,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let mut data = [0; 74];
+    // Since we're writing to an array and not to a file or socket, these
+    // write operations should never fail.
+    (&mut data[0..2])
+        .write_u16::<LittleEndian>(duration_seconds)
+        .expect("Writing to buffer failed");
+    (&mut data[2..18])
+        .write_all(&blob_id.0)
+        .expect("Writing to buffer failed");
+    (&mut data[18..22])
+        .write_u32::<LittleEndian>(video_size_bytes)
+        .expect("Writing to buffer failed");
+    (&mut data[22..38])
+        .write_all(&thumbnail_blob_id.0)
+        .expect("Writing to buffer failed");
+    (&mut data[38..42])
+        .write_u32::<LittleEndian>(thumbnail_size_bytes)
+        .expect("Writing to buffer failed");
+    (&mut data[42..74])
+        .write_all(&blob_encryption_key.0)
+        .expect("Writing to buffer failed");
+    let msgtype = MessageType::Video;
+    encrypt(&data, msgtype, public_key, private_key)
+}
+
+/// Encrypt a location message for the recipient.
+///
+/// The latitude must be in the range `[-90, 90]` and the longitude in the
+/// range `[-180, 180]`. The accuracy is specified in meters.
+pub fn encrypt_location_msg(
+    latitude: f64,
+    longitude: f64,
+    accuracy: Option<f64>,
+    name: Option<&str>,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> Result<EncryptedMessage, CryptoError> {
+    if !(-90.0..=90.0).contains(&latitude) {
+        return Err(CryptoError::BadMessage(format!(
+            "Latitude must be between -90 and 90, but is {}",
+            latitude
+        )));
+    }
+    if !(-180.0..=180.0).contains(&longitude) {
+        return Err(CryptoError::BadMessage(format!(
+            "Longitude must be between -180 and 180, but is {}",
+            longitude
+        )));
+    }
+    let mut data = format!("{},{}", latitude, longitude);
+    if let Some(accuracy) = accuracy {
+        data.push_str(&format!(",{}", accuracy));
+    }
+    if let Some(name) = name {
+        data.push('\n');
+        data.push_str(name);
+    }
+    let msgtype = MessageType::Location;
+    Ok(encrypt(data.as_bytes(), msgtype, public_key, private_key))
+}
+
+/// Return the header of a group message, consisting of the Threema ID of the
+/// group creator and the group ID.
+fn group_header(group_creator: &str, group_id: &[u8; 8]) -> Result<Vec<u8>, CryptoError> {
+    if !is_valid_id(group_creator) {
+        return Err(CryptoError::BadMessage(format!(
+            "Group creator is not a valid Threema ID: \"{}\"",
+            group_creator
+        )));
+    }
+    Ok(group_creator
+        .as_bytes()
+        .iter()
+        .chain(group_id.iter())
+        .cloned()
+        .collect())
+}
+
+/// Encrypt a group text message for the recipient.
+///
+/// The group is identified by the Threema ID of its creator (8 characters)
+/// and the 8 byte group ID.
+pub fn encrypt_group_text_msg(
+    group_creator: &str,
+    group_id: &[u8; 8],
+    text: &str,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> Result<EncryptedMessage, CryptoError> {
+    let mut data = group_header(group_creator, group_id)?;
+    data.extend_from_slice(text.as_bytes());
+    let msgtype = MessageType::GroupText;
+    Ok(encrypt(&data, msgtype, public_key, private_key))
+}
+
+/// Encrypt a group image message for the recipient.
+///
+/// The group header (creator and group ID) is followed by the blob ID, the
+/// image size and the key that was used to encrypt the image data.
+#[allow(clippy::too_many_arguments)]
+pub fn encrypt_group_image_msg(
+    group_creator: &str,
+    group_id: &[u8; 8],
+    blob_id: &BlobId,
+    img_size_bytes: u32,
+    blob_encryption_key: &Key,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> Result<EncryptedMessage, CryptoError> {
+    let mut data = group_header(group_creator, group_id)?;
+    data.extend_from_slice(&blob_id.0);
+    data.write_u32::<LittleEndian>(img_size_bytes)
+        .expect("Writing to buffer failed");
+    data.extend_from_slice(&blob_encryption_key.0);
+    let msgtype = MessageType::GroupImage;
+    Ok(encrypt(&data, msgtype, public_key, private_key))
+}
+
+/// Encrypt a group setup message for the recipient.
+///
+/// The message is sent by the group creator and contains the group ID,
+/// followed by the Threema IDs of all group members (excluding the creator).
+/// An empty member list dissolves the group.
+pub fn encrypt_group_setup_msg(
+    group_id: &[u8; 8],
+    members: &[&str],
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> Result<EncryptedMessage, CryptoError> {
+    let mut data = group_id.to_vec();
+    for member in members {
+        if !is_valid_id(member) {
+            return Err(CryptoError::BadMessage(format!(
+                "Group member is not a valid Threema ID: \"{}\"",
+                member
+            )));
+        }
+        data.extend_from_slice(member.as_bytes());
+    }
+    let msgtype = MessageType::GroupSetup;
+    Ok(encrypt(&data, msgtype, public_key, private_key))
+}
+
+/// Encrypt a group rename message for the recipient.
+///
+/// The message is sent by the group creator and contains the group ID,
+/// followed by the new group name.
+pub fn encrypt_group_name_msg(
+    group_id: &[u8; 8],
+    name: &str,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let mut data = group_id.to_vec();
+    data.extend_from_slice(name.as_bytes());
+    let msgtype = MessageType::GroupName;
+    encrypt(&data, msgtype, public_key, private_key)
+}
+
+/// Encrypt a group set photo message for the recipient.
+///
+/// The message is sent by the group creator. The group ID is followed by the
+/// blob ID, the image size and the key that was used to encrypt the image
+/// data.
+pub fn encrypt_group_set_photo_msg(
+    group_id: &[u8; 8],
+    blob_id: &BlobId,
+    img_size_bytes: u32,
+    blob_encryption_key: &Key,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let mut data = group_id.to_vec();
+    data.extend_from_slice(&blob_id.0);
+    data.write_u32::<LittleEndian>(img_size_bytes)
+        .expect("Writing to buffer failed");
+    data.extend_from_slice(&blob_encryption_key.0);
+    let msgtype = MessageType::GroupSetPhoto;
+    encrypt(&data, msgtype, public_key, private_key)
+}
+
+/// Encrypt a group delete photo message for the recipient.
+///
+/// The message is sent by the group creator and contains only the group ID.
+pub fn encrypt_group_delete_photo_msg(
+    group_id: &[u8; 8],
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let msgtype = MessageType::GroupDeletePhoto;
+    encrypt(group_id, msgtype, public_key, private_key)
+}
+
 /// Encrypt a file message for the recipient.
 pub fn encrypt_file_msg(
     msg: &FileMessage,
@@ -150,16 +676,304 @@ pub fn encrypt_file_msg(
 ) -> EncryptedMessage {
     let data = json::to_string(msg).unwrap();
     let msgtype = MessageType::File;
-    encrypt(&data.as_bytes(), msgtype, &public_key, &private_key)
+    encrypt(data.as_bytes(), msgtype, public_key, private_key)
+}
+
+/// Encrypt a file message for the recipient, using the specified nonce and
+/// amount of padding.
+///
+/// See [`encrypt_with_nonce`](fn.encrypt_with_nonce.html) for details.
+pub fn encrypt_file_msg_with_nonce(
+    msg: &FileMessage,
+    nonce: &[u8; 24],
+    padding_amount: NonZeroU8,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let data = json::to_string(msg).unwrap();
+    let msgtype = MessageType::File;
+    encrypt_with_nonce(
+        data.as_bytes(),
+        msgtype,
+        nonce,
+        padding_amount,
+        public_key,
+        private_key,
+    )
+}
+
+/// Encrypt a ballot create message for the recipient.
+pub fn encrypt_ballot_create_msg(
+    msg: &BallotMessage,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let mut data = msg.ballot_id().to_vec();
+    json::to_writer(&mut data, msg).unwrap();
+    let msgtype = MessageType::BallotCreate;
+    encrypt(&data, msgtype, public_key, private_key)
+}
+
+/// Encrypt a delivery receipt for the recipient.
+pub fn encrypt_delivery_receipt(
+    receipt: &DeliveryReceipt,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let msgtype = MessageType::DeliveryReceipt;
+    encrypt(&receipt.to_bytes(), msgtype, public_key, private_key)
+}
+
+/// Encrypt a forward security control message for the recipient.
+pub fn encrypt_fs_control(
+    msg: &FsControlMessage,
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> EncryptedMessage {
+    let msgtype = MessageType::ForwardSecurityEnvelope;
+    encrypt(&msg.to_bytes(), msgtype, public_key, private_key)
+}
+
+/// Decrypt data from the sender.
+pub fn decrypt_raw(
+    data: &[u8],
+    nonce: &[u8; 24],
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> Result<Vec<u8>, CryptoError> {
+    backend::open(data, nonce, public_key, private_key).map_err(|_| CryptoError::DecryptionFailed)
+}
+
+/// Decrypt a message from the sender.
+///
+/// The padding is validated and removed, and the message type byte is used to
+/// parse the message payload.
+pub fn decrypt(
+    data: &[u8],
+    nonce: &[u8; 24],
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> Result<DecryptedMessage, CryptoError> {
+    let padded_plaintext = decrypt_raw(data, nonce, public_key, private_key)?;
+    parse_plaintext(&padded_plaintext)
+}
+
+/// Decrypt a message from the sender, using a precomputed key.
+///
+/// See [`decrypt`](fn.decrypt.html) for details.
+pub fn decrypt_precomputed(
+    data: &[u8],
+    nonce: &[u8; 24],
+    key: &PrecomputedKey,
+) -> Result<DecryptedMessage, CryptoError> {
+    let padded_plaintext =
+        backend::open_precomputed(data, nonce, key).map_err(|_| CryptoError::DecryptionFailed)?;
+    parse_plaintext(&padded_plaintext)
+}
+
+/// Remove the padding of a decrypted message and parse it.
+fn parse_plaintext(padded_plaintext: &[u8]) -> Result<DecryptedMessage, CryptoError> {
+    let plaintext = unpad(padded_plaintext)?;
+
+    // Parse message type and payload
+    let (msgtype, payload) = plaintext.split_first().ok_or(CryptoError::EmptyMessage)?;
+    match MessageType::from_byte(*msgtype) {
+        Some(MessageType::Text) => String::from_utf8(payload.to_vec())
+            .map(DecryptedMessage::Text)
+            .map_err(|_| CryptoError::BadMessage("Text message is not valid UTF-8".into())),
+        Some(MessageType::Image) => decode_image_msg(payload),
+        Some(MessageType::DeliveryReceipt) => {
+            DeliveryReceipt::from_bytes(payload).map(DecryptedMessage::DeliveryReceipt)
+        }
+        _ => Ok(DecryptedMessage::Other {
+            msgtype: *msgtype,
+            data: payload.to_vec(),
+        }),
+    }
+}
+
+/// Decrypt a text message from the sender, replacing invalid UTF-8
+/// sequences with the replacement character `U+FFFD`.
+///
+/// If the message is not a text message, an error is returned.
+pub fn decrypt_text_lossy(
+    data: &[u8],
+    nonce: &[u8; 24],
+    public_key: &PublicKey,
+    private_key: &SecretKey,
+) -> Result<String, CryptoError> {
+    let padded_plaintext = decrypt_raw(data, nonce, public_key, private_key)?;
+    let plaintext = unpad(&padded_plaintext)?;
+    match plaintext.split_first() {
+        Some((msgtype, payload)) if *msgtype == MessageType::Text.to_byte() => {
+            Ok(String::from_utf8_lossy(payload).into_owned())
+        }
+        Some((msgtype, _)) => Err(CryptoError::BadMessage(format!(
+            "Expected a text message, but message type is {:#04x}",
+            msgtype
+        ))),
+        None => Err(CryptoError::EmptyMessage),
+    }
+}
+
+/// Parse the payload of an image message.
+fn decode_image_msg(mut payload: &[u8]) -> Result<DecryptedMessage, CryptoError> {
+    if payload.len() != 44 {
+        return Err(CryptoError::BadMessage(format!(
+            "Image message payload must be 44 bytes, but is {} bytes",
+            payload.len()
+        )));
+    }
+    // Since we verified the length above, these read operations cannot fail.
+    let mut blob_id = [0; 16];
+    payload
+        .read_exact(&mut blob_id)
+        .expect("Reading from buffer failed");
+    let img_size_bytes = payload
+        .read_u32::<LittleEndian>()
+        .expect("Reading from buffer failed");
+    let mut image_data_nonce = [0; 24];
+    payload
+        .read_exact(&mut image_data_nonce)
+        .expect("Reading from buffer failed");
+    Ok(DecryptedMessage::Image {
+        blob_id: BlobId::new(blob_id),
+        img_size_bytes,
+        image_data_nonce,
+    })
+}
+
+/// The nonce used to encrypt the blob data of file, audio and video messages.
+pub(crate) const BLOB_DATA_NONCE: [u8; 24] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+];
+
+/// Decrypt the blob data of a file, audio or video message with the
+/// symmetric key contained in the message.
+///
+/// The data is decrypted in place, so the returned plaintext reuses the
+/// allocation of the ciphertext and no second copy of the data is held in
+/// memory.
+///
+/// Note that the data cannot be decrypted in a streaming fashion: It is
+/// encrypted as a single NaCl secretbox, whose authentication tag covers the
+/// entire ciphertext. Decrypting chunks before the whole blob has been
+/// verified would hand out unauthenticated data.
+pub fn decrypt_blob_data(mut data: Vec<u8>, key: &Key) -> Result<Vec<u8>, CryptoError> {
+    backend::secretbox_open_in_place(&mut data, &BLOB_DATA_NONCE, key)
+        .map_err(|_| CryptoError::DecryptionFailed)?;
+    Ok(data)
+}
+
+/// How the blob data of a media message is encrypted.
+#[derive(Debug)]
+enum MediaEncryption {
+    /// Encrypted with the key pair of sender and recipient, using the
+    /// specified nonce (image messages).
+    Box([u8; 24]),
+    /// Encrypted with the specified symmetric key, using the nonce `000...1`
+    /// (file, audio and video messages).
+    SecretBox(Key),
+}
+
+/// A reference to the encrypted blob data of a media message.
+#[derive(Debug)]
+pub(crate) struct MediaRef {
+    pub(crate) blob_id: BlobId,
+    encryption: MediaEncryption,
+}
+
+impl MediaRef {
+    /// Extract the blob reference from an image, file, audio or video
+    /// message. Other messages return an error.
+    pub(crate) fn from_message(msg: &DecryptedMessage) -> Result<Self, CryptoError> {
+        let (msgtype, data) = match msg {
+            DecryptedMessage::Image {
+                blob_id,
+                image_data_nonce,
+                ..
+            } => {
+                return Ok(MediaRef {
+                    blob_id: blob_id.clone(),
+                    encryption: MediaEncryption::Box(*image_data_nonce),
+                })
+            }
+            DecryptedMessage::Other { msgtype, data } => (*msgtype, &data[..]),
+            _ => {
+                return Err(CryptoError::BadMessage(
+                    "Message does not contain media".into(),
+                ))
+            }
+        };
+        let (blob_id, key) = match MessageType::from_byte(msgtype) {
+            Some(MessageType::Audio) if data.len() == 54 => (&data[2..18], &data[22..54]),
+            Some(MessageType::Video) if data.len() == 74 => (&data[2..18], &data[42..74]),
+            Some(MessageType::Audio) | Some(MessageType::Video) => {
+                return Err(CryptoError::BadMessage(format!(
+                    "Media message payload has invalid length {}",
+                    data.len()
+                )))
+            }
+            Some(MessageType::File) => return Self::from_file_msg(data),
+            _ => {
+                return Err(CryptoError::BadMessage(format!(
+                    "Message type {:#04x} does not contain media",
+                    msgtype
+                )))
+            }
+        };
+        let mut blob_id_bytes = [0; 16];
+        blob_id_bytes.copy_from_slice(blob_id);
+        Ok(MediaRef {
+            blob_id: BlobId::new(blob_id_bytes),
+            encryption: MediaEncryption::SecretBox(
+                Key::from_slice(key).expect("Key has the correct length"),
+            ),
+        })
+    }
+
+    /// Extract the blob reference from the JSON payload of a file message.
+    fn from_file_msg(data: &[u8]) -> Result<Self, CryptoError> {
+        let bad_message = |msg: &str| CryptoError::BadMessage(format!("File message {}", msg));
+        let value: json::Value =
+            json::from_slice(data).map_err(|_| bad_message("is not valid JSON"))?;
+        let blob_id = value["b"]
+            .as_str()
+            .and_then(|id| BlobId::from_str(id).ok())
+            .ok_or_else(|| bad_message("has an invalid blob ID"))?;
+        let key = value["k"]
+            .as_str()
+            .and_then(|key| HEXLOWER_PERMISSIVE.decode(key.as_bytes()).ok())
+            .and_then(|key| Key::from_slice(&key))
+            .ok_or_else(|| bad_message("has an invalid key"))?;
+        Ok(MediaRef {
+            blob_id,
+            encryption: MediaEncryption::SecretBox(key),
+        })
+    }
+
+    /// Decrypt the downloaded blob data.
+    pub(crate) fn decrypt(
+        &self,
+        data: Vec<u8>,
+        public_key: &PublicKey,
+        private_key: &SecretKey,
+    ) -> Result<Vec<u8>, CryptoError> {
+        match self.encryption {
+            MediaEncryption::Box(ref nonce) => decrypt_raw(&data, nonce, public_key, private_key),
+            MediaEncryption::SecretBox(ref key) => decrypt_blob_data(data, key),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
 
-    use crate::api::ApiBuilder;
+    use crate::api::{ApiBuilder, AsyncE2eApi};
+    use crate::backend;
     use crate::types::{BlobId, MessageType};
-    use sodiumoxide::crypto::box_::{self, Nonce, PublicKey, SecretKey};
+    use crate::{PublicKey, SecretKey};
 
     use super::*;
 
@@ -181,6 +995,57 @@ mod test {
         assert!(!random_numbers.iter().all(|n| *n == first));
     }
 
+    #[test]
+    fn test_pad_unpad_bounds() {
+        for padding_amount in &[1, 2, 254, 255] {
+            let padded = pad(b"data", MessageType::Text, *padding_amount);
+            assert_eq!(padded.len(), 1 + 4 + *padding_amount as usize);
+            assert_eq!(padded.last(), Some(padding_amount));
+            assert_eq!(unpad(&padded).unwrap(), b"\x01data");
+        }
+        // Empty message data, only the message type remains
+        assert_eq!(unpad(&pad(b"", MessageType::Text, 1)).unwrap(), b"\x01");
+    }
+
+    #[test]
+    fn test_unpad_invalid() {
+        assert!(matches!(unpad(&[]), Err(CryptoError::EmptyMessage)));
+        assert!(matches!(unpad(&[1, 2, 0]), Err(CryptoError::BadPadding)));
+        // Padding must not consume the message type byte
+        assert!(matches!(unpad(&[1]), Err(CryptoError::BadPadding)));
+        assert!(matches!(unpad(&[2, 2]), Err(CryptoError::BadPadding)));
+        assert!(matches!(unpad(&[1, 2, 3, 3]), Err(CryptoError::BadPadding)));
+        assert!(matches!(unpad(&[1, 2, 3, 2]), Err(CryptoError::BadPadding)));
+        assert_eq!(unpad(&[1, 2, 3, 2, 2]).unwrap(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_random_lengths() {
+        let (a_pub, a_sec) = backend::gen_keypair();
+        let (b_pub, b_sec) = backend::gen_keypair();
+        for _ in 0..200 {
+            let mut len = [0; 2];
+            randombytes_into(&mut len);
+            let mut data = vec![0; u16::from_le_bytes(len) as usize % 1024];
+            randombytes_into(&mut data);
+            let encrypted = encrypt(&data, MessageType::File, &b_pub, &a_sec);
+            let padded = decrypt_raw(&encrypted.ciphertext, &encrypted.nonce, &a_pub, &b_sec);
+            let padded = padded.unwrap();
+            let padding_amount = padded.len() - data.len() - 1;
+            assert!((1..=255).contains(&padding_amount));
+            match decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub, &b_sec) {
+                Ok(DecryptedMessage::Other {
+                    msgtype,
+                    data: decrypted,
+                }) => {
+                    assert_eq!(msgtype, u8::from(MessageType::File));
+                    assert_eq!(decrypted, data);
+                }
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+    }
+
     #[test]
     fn test_encrypt_image_msg() {
         // Set up keys
@@ -196,21 +1061,21 @@ mod test {
         // Set up API
         let api = ApiBuilder::new("*3MAGWID", "1234")
             .with_private_key(own_sec.clone())
-            .into_e2e()
+            .into_e2e_async()
             .unwrap();
 
         // Fake a blob upload
         let blob_id = BlobId::from_str("00112233445566778899aabbccddeeff").unwrap();
-        let blob_nonce = box_::gen_nonce();
+        let blob_nonce = backend::gen_nonce();
 
         // Encrypt
         let recipient_key = RecipientKey(other_pub);
-        let encrypted = api.encrypt_image_msg(&blob_id, 258, &blob_nonce.0, &recipient_key);
+        let encrypted = api.encrypt_image_msg(&blob_id, 258, &blob_nonce, &recipient_key);
 
         // Decrypt
-        let decrypted = box_::open(
+        let decrypted = backend::open(
             &encrypted.ciphertext,
-            &Nonce(encrypted.nonce),
+            &encrypted.nonce,
             &other_pub,
             &own_sec,
         )
@@ -229,7 +1094,666 @@ mod test {
         assert_eq!(data.len(), 44 + 1);
         assert_eq!(&data[1..17], &blob_id.0);
         assert_eq!(&data[17..21], &[2, 1, 0, 0]);
-        assert_eq!(&data[21..45], &blob_nonce.0);
+        assert_eq!(&data[21..45], &blob_nonce);
+    }
+
+    /// Create two API objects with random keys, return them together with
+    /// their public keys.
+    fn api_pair() -> ((AsyncE2eApi, RecipientKey), (AsyncE2eApi, RecipientKey)) {
+        let (a_pub, a_sec) = backend::gen_keypair();
+        let (b_pub, b_sec) = backend::gen_keypair();
+        let a = ApiBuilder::new("*3MAGWID", "1234")
+            .with_private_key(a_sec)
+            .into_e2e_async()
+            .unwrap();
+        let b = ApiBuilder::new("*3MAGWID", "1234")
+            .with_private_key(b_sec)
+            .into_e2e_async()
+            .unwrap();
+        ((a, RecipientKey(a_pub)), (b, RecipientKey(b_pub)))
+    }
+
+    #[test]
+    fn test_decrypt_text_msg() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let encrypted = a.encrypt_text_msg("Hello, Bob! 🙂", &b_pub);
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(decrypted, DecryptedMessage::Text("Hello, Bob! 🙂".into()));
+    }
+
+    #[test]
+    fn test_decrypt_invalid_utf8() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let encrypted = a.encrypt_raw(&[0x01, b'h', 0xff, b'i', 0x01], &b_pub);
+        assert!(matches!(
+            b.decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub),
+            Err(CryptoError::BadMessage(_))
+        ));
+        let text = b
+            .decrypt_text_lossy(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(text, "h\u{fffd}i");
+
+        let encrypted = a.encrypt_image_msg(&BlobId::new([0; 16]), 1, &[0; 24], &b_pub);
+        assert!(b
+            .decrypt_text_lossy(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .is_err());
+    }
+
+    #[test]
+    fn test_precomputed() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let a_key = a.precompute(&b_pub);
+        let b_key = b.precompute(&a_pub);
+
+        let encrypted = a.encrypt_text_msg_precomputed("Hello, Bob!", &a_key);
+        assert_eq!(encrypted.msg_type, Some(MessageType::Text));
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(decrypted, DecryptedMessage::Text("Hello, Bob!".into()));
+
+        let encrypted = b.encrypt_text_msg("Hello, Alice!", &a_pub);
+        let decrypted = a
+            .decrypt_precomputed(&encrypted.ciphertext, &encrypted.nonce, &a_key)
+            .unwrap();
+        assert_eq!(decrypted, DecryptedMessage::Text("Hello, Alice!".into()));
+
+        let encrypted = a.encrypt_raw_precomputed(b"raw", &a_key);
+        let decrypted = backend::open_precomputed(&encrypted.ciphertext, &encrypted.nonce, &b_key);
+        assert_eq!(decrypted.unwrap(), b"raw");
+        assert_eq!(format!("{:?}", b_key), "PrecomputedKey(****)");
+    }
+
+    /// Known answer tests for the message framing (type byte, payload and
+    /// padding) of the different message types.
+    ///
+    /// The sender key is `[1; 32]`, the recipient key `[2; 32]`. The nonce
+    /// consists of 24 times the nonce byte, the padding amount is the nonce
+    /// byte + 1. Both crypto backends must produce identical ciphertexts.
+    #[test]
+    fn test_message_vectors() {
+        use crate::types::{MessageId, ReceiptStatus};
+
+        let sender_key = SecretKey([1; 32]);
+        let recipient_key = SecretKey([2; 32]);
+        let image_payload = image_msg_payload(&BlobId::new([0x11; 16]), 1000, &[0x22; 24]);
+        let receipt_payload = [&[0x02][..], &[0x33; 8]].concat();
+        let group_payload = b"GRPCREAT\x01\x02\x03\x04\x05\x06\x07\x08Hi".to_vec();
+        let vectors: [(MessageType, &[u8], u8, &str); 6] = [
+            (
+                MessageType::Text,
+                b"Hello",
+                1,
+                "06fc730241d65fa4f7ad46eee46d5bd83075571729c36065",
+            ),
+            (
+                MessageType::Image,
+                &image_payload,
+                2,
+                "0759a0cc0975cab3783a9732bd6416bc820b4de4bc59864b742127ebf87c2940\
+                 64d2d57cc1e35453adb8327ccc63bbacaa269f2aafd0aa569cc770f8b2e4f15d",
+            ),
+            (
+                MessageType::DeliveryReceipt,
+                &receipt_payload,
+                3,
+                "d7f1de2b13049c784a290cadc98fb50d63294c3c1825099d173322737eae",
+            ),
+            (
+                MessageType::Location,
+                b"47.36,8.54",
+                4,
+                "e9f3cb25304cb1560a10a7c98784f5f6356bcc4e2261b4d1a701522c8dbf4901",
+            ),
+            (
+                MessageType::GroupText,
+                &group_payload,
+                5,
+                "7ac2c0646cc2e6afa6e811f54423246632ce6ce798db56baf792529844474e87\
+                 6a1e5962ff342bd1ac",
+            ),
+            (
+                MessageType::File,
+                b"{\"j\":0}",
+                6,
+                "fbb9d79ce6cf53558cb78c6e8b8ca43f6d8d9c4878a9e83519d3fdadfb33d1",
+            ),
+        ];
+
+        for (msgtype, payload, nonce_byte, ciphertext) in vectors.iter() {
+            let nonce = [*nonce_byte; 24];
+            let padding_amount = nonce_byte + 1;
+            let ciphertext = HEXLOWER.decode(ciphertext.as_bytes()).unwrap();
+
+            // Encryption
+            let encrypted = encrypt_with_nonce(
+                payload,
+                *msgtype,
+                &nonce,
+                NonZeroU8::new(padding_amount).unwrap(),
+                &recipient_key.public_key(),
+                &sender_key,
+            );
+            assert_eq!(encrypted.nonce, nonce);
+            assert_eq!(encrypted.ciphertext, ciphertext, "{:?}", msgtype);
+
+            // Framing
+            let padded = decrypt_raw(
+                &ciphertext,
+                &nonce,
+                &sender_key.public_key(),
+                &recipient_key,
+            )
+            .unwrap();
+            let mut expected = vec![msgtype.to_byte()];
+            expected.extend_from_slice(payload);
+            expected.extend(repeat_n(padding_amount, padding_amount as usize));
+            assert_eq!(padded, expected, "{:?}", msgtype);
+
+            // Parsing
+            let decrypted = decrypt(
+                &ciphertext,
+                &nonce,
+                &sender_key.public_key(),
+                &recipient_key,
+            )
+            .unwrap();
+            let expected = match msgtype {
+                MessageType::Text => DecryptedMessage::Text("Hello".into()),
+                MessageType::Image => DecryptedMessage::Image {
+                    blob_id: BlobId::new([0x11; 16]),
+                    img_size_bytes: 1000,
+                    image_data_nonce: [0x22; 24],
+                },
+                MessageType::DeliveryReceipt => {
+                    DecryptedMessage::DeliveryReceipt(DeliveryReceipt {
+                        status: ReceiptStatus::Read,
+                        message_ids: vec![MessageId([0x33; 8])],
+                    })
+                }
+                _ => DecryptedMessage::Other {
+                    msgtype: msgtype.to_byte(),
+                    data: payload.to_vec(),
+                },
+            };
+            assert_eq!(decrypted, expected, "{:?}", msgtype);
+
+            // Re-encryption of the decrypted payload yields the same ciphertext
+            let reencrypted = encrypt_with_nonce(
+                &padded[1..padded.len() - padding_amount as usize],
+                *msgtype,
+                &nonce,
+                NonZeroU8::new(padding_amount).unwrap(),
+                &recipient_key.public_key(),
+                &sender_key,
+            );
+            assert_eq!(reencrypted.ciphertext, ciphertext, "{:?}", msgtype);
+        }
+    }
+
+    /// Backup of the ID `ECHOECHO` with the private key `[7; 32]`, encrypted
+    /// with the password `correct horse` and the salt `[1, 2, ..., 8]`.
+    const ID_BACKUP: &str = "AEBA-GBAF-AYDQ-QSDE-LDQL-Q6MB-LCCV-4LO6-F4ZJ-OSKC-\
+                             MV4E-HA2I-BERT-46OL-OWBV-RFE3-HOUL-MZKP-RVTR-FCTS";
+
+    #[test]
+    fn test_decrypt_id_backup() {
+        let (id, private_key) = decrypt_id_backup(ID_BACKUP, "correct horse").unwrap();
+        assert_eq!(id, "ECHOECHO");
+        assert_eq!(private_key.0, [7; 32]);
+
+        // Lowercase and without dashes
+        let compact = ID_BACKUP.replace('-', "").to_lowercase();
+        assert_eq!(
+            decrypt_id_backup(&compact, "correct horse").unwrap().0,
+            "ECHOECHO"
+        );
+
+        assert!(matches!(
+            decrypt_id_backup(ID_BACKUP, "wrong horse"),
+            Err(ApiBuilderError::WrongBackupPassword)
+        ));
+        assert!(matches!(
+            decrypt_id_backup(&ID_BACKUP[..50], "correct horse"),
+            Err(ApiBuilderError::InvalidBackup(_))
+        ));
+        assert!(matches!(
+            decrypt_id_backup("not base32!", "correct horse"),
+            Err(ApiBuilderError::InvalidBackup(_))
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_blob_data() {
+        let key = Key([3; 32]);
+        let encrypted = backend::secretbox_seal(b"file data", &BLOB_DATA_NONCE, &key);
+        assert_eq!(
+            decrypt_blob_data(encrypted.clone(), &key).unwrap(),
+            b"file data"
+        );
+
+        let mut tampered = encrypted;
+        tampered[20] ^= 1;
+        assert!(matches!(
+            decrypt_blob_data(tampered, &key),
+            Err(CryptoError::DecryptionFailed)
+        ));
+        assert!(decrypt_blob_data(vec![0; 10], &key).is_err());
+        assert!(matches!(
+            decrypt_blob_data(
+                backend::secretbox_seal(b"", &BLOB_DATA_NONCE, &key),
+                &Key([4; 32])
+            ),
+            Err(CryptoError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_image_msg() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let blob_id = BlobId::from_str("00112233445566778899aabbccddeeff").unwrap();
+        let blob_nonce = backend::gen_nonce();
+        let encrypted = a.encrypt_image_msg(&blob_id, 1234, &blob_nonce, &b_pub);
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(
+            decrypted,
+            DecryptedMessage::Image {
+                blob_id,
+                img_size_bytes: 1234,
+                image_data_nonce: blob_nonce,
+            }
+        );
+    }
+
+    #[test]
+    fn test_encrypt_image_message() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let msg = crate::ImageMessage::builder()
+            .blob_id(BlobId::new([1; 16]))
+            .size(1234)
+            .nonce([2; 24])
+            .build()
+            .unwrap();
+        let encrypted = a.encrypt_image_message(&msg, &b_pub);
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(
+            decrypted,
+            DecryptedMessage::Image {
+                blob_id: BlobId::new([1; 16]),
+                img_size_bytes: 1234,
+                image_data_nonce: [2; 24],
+            }
+        );
+    }
+
+    #[test]
+    fn test_encrypt_audio_msg() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let blob_id = BlobId::from_str("00112233445566778899aabbccddeeff").unwrap();
+        let key = Key([7; 32]);
+        let encrypted = a.encrypt_audio_msg(&blob_id, 300, 0x01020304, &key, &b_pub);
+        match b.decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub) {
+            Ok(DecryptedMessage::Other { msgtype, data }) => {
+                assert_eq!(msgtype, u8::from(MessageType::Audio));
+                assert_eq!(data.len(), 54);
+                assert_eq!(&data[0..2], &[0x2c, 0x01]);
+                assert_eq!(&data[2..18], &blob_id.0);
+                assert_eq!(&data[18..22], &[0x04, 0x03, 0x02, 0x01]);
+                assert_eq!(&data[22..54], &[7; 32]);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_video_msg() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let blob_id = BlobId::from_str("00112233445566778899aabbccddeeff").unwrap();
+        let thumbnail_blob_id = BlobId::from_str("ffeeddccbbaa99887766554433221100").unwrap();
+        let key = Key([7; 32]);
+        let encrypted = a.encrypt_video_msg(
+            &blob_id,
+            &thumbnail_blob_id,
+            90,
+            1_000_000,
+            2048,
+            &key,
+            &b_pub,
+        );
+        match b.decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub) {
+            Ok(DecryptedMessage::Other { msgtype, data }) => {
+                assert_eq!(msgtype, u8::from(MessageType::Video));
+                assert_eq!(data.len(), 74);
+                assert_eq!(&data[0..2], &[90, 0]);
+                assert_eq!(&data[2..18], &blob_id.0);
+                assert_eq!(&data[18..22], &1_000_000u32.to_le_bytes());
+                assert_eq!(&data[22..38], &thumbnail_blob_id.0);
+                assert_eq!(&data[38..42], &2048u32.to_le_bytes());
+                assert_eq!(&data[42..74], &[7; 32]);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_location_msg() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let encrypted = a
+            .encrypt_location_msg(47.3769, 8.5417, Some(12.5), Some("Zürich"), &b_pub)
+            .unwrap();
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(
+            decrypted,
+            DecryptedMessage::Other {
+                msgtype: MessageType::Location.into(),
+                data: "47.3769,8.5417,12.5\nZürich".as_bytes().to_vec(),
+            }
+        );
+
+        let encrypted = a
+            .encrypt_location_msg(-90.0, 180.0, None, None, &b_pub)
+            .unwrap();
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(
+            decrypted,
+            DecryptedMessage::Other {
+                msgtype: MessageType::Location.into(),
+                data: b"-90,180".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_encrypt_location_msg_out_of_range() {
+        let ((a, _), (_, b_pub)) = api_pair();
+        assert!(a
+            .encrypt_location_msg(90.1, 0.0, None, None, &b_pub)
+            .is_err());
+        assert!(a
+            .encrypt_location_msg(-90.1, 0.0, None, None, &b_pub)
+            .is_err());
+        assert!(a
+            .encrypt_location_msg(0.0, 180.1, None, None, &b_pub)
+            .is_err());
+        assert!(a
+            .encrypt_location_msg(0.0, -180.1, None, None, &b_pub)
+            .is_err());
+        assert!(a
+            .encrypt_location_msg(f64::NAN, 0.0, None, None, &b_pub)
+            .is_err());
+    }
+
+    #[test]
+    fn test_encrypt_group_text_msg() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let group_id = [1, 2, 3, 4, 5, 6, 7, 8];
+        let encrypted = a
+            .encrypt_group_text_msg("GRPCREAT", &group_id, "Hello, group!", &b_pub)
+            .unwrap();
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(
+            decrypted,
+            DecryptedMessage::Other {
+                msgtype: MessageType::GroupText.into(),
+                data: b"GRPCREAT\x01\x02\x03\x04\x05\x06\x07\x08Hello, group!".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_encrypt_group_image_msg() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let blob_id = BlobId::from_str("00112233445566778899aabbccddeeff").unwrap();
+        let key = Key([7; 32]);
+        let encrypted = a
+            .encrypt_group_image_msg("GRPCREAT", &[1; 8], &blob_id, 258, &key, &b_pub)
+            .unwrap();
+        match b.decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub) {
+            Ok(DecryptedMessage::Other { msgtype, data }) => {
+                assert_eq!(msgtype, 0x43);
+                assert_eq!(data.len(), 68);
+                assert_eq!(&data[..8], b"GRPCREAT");
+                assert_eq!(&data[8..16], &[1; 8]);
+                assert_eq!(&data[16..32], &blob_id.0);
+                assert_eq!(&data[32..36], &[2, 1, 0, 0]);
+                assert_eq!(&data[36..], &[7; 32]);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_group_control_msgs() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let group_id = [1, 2, 3, 4, 5, 6, 7, 8];
+        let decrypt_other = |encrypted: EncryptedMessage| match b.decrypt(
+            &encrypted.ciphertext,
+            &encrypted.nonce,
+            &a_pub,
+        ) {
+            Ok(DecryptedMessage::Other { msgtype, data }) => (msgtype, data),
+            other => panic!("Unexpected result: {:?}", other),
+        };
+
+        let encrypted = a
+            .encrypt_group_setup_msg(&group_id, &["ECHOECHO", "TESTTEST"], &b_pub)
+            .unwrap();
+        assert_eq!(encrypted.msg_type, Some(MessageType::GroupSetup));
+        assert_eq!(
+            decrypt_other(encrypted),
+            (
+                0x4a,
+                b"\x01\x02\x03\x04\x05\x06\x07\x08ECHOECHOTESTTEST".to_vec()
+            )
+        );
+        match a.encrypt_group_setup_msg(&group_id, &["ECHOECHO", "ÄBCDEFG"], &b_pub) {
+            Err(CryptoError::BadMessage(msg)) => assert!(msg.contains("ÄBCDEFG"), "{}", msg),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let encrypted = a.encrypt_group_name_msg(&group_id, "Lunch", &b_pub);
+        assert_eq!(
+            decrypt_other(encrypted),
+            (0x4b, b"\x01\x02\x03\x04\x05\x06\x07\x08Lunch".to_vec())
+        );
+
+        let blob_id = BlobId::new([9; 16]);
+        let key = Key([10; 32]);
+        let encrypted = a.encrypt_group_set_photo_msg(&group_id, &blob_id, 258, &key, &b_pub);
+        let (msgtype, data) = decrypt_other(encrypted);
+        assert_eq!(msgtype, 0x50);
+        assert_eq!(data.len(), 8 + 16 + 4 + 32);
+        assert_eq!(&data[..8], &group_id);
+        assert_eq!(&data[8..24], &[9; 16]);
+        assert_eq!(&data[24..28], &[2, 1, 0, 0]);
+        assert_eq!(&data[28..], &[10; 32]);
+
+        let encrypted = a.encrypt_group_delete_photo_msg(&group_id, &b_pub);
+        assert_eq!(decrypt_other(encrypted), (0x54, group_id.to_vec()));
+    }
+
+    #[test]
+    fn test_encrypt_group_text_msg_bad_creator() {
+        let ((a, _), (_, b_pub)) = api_pair();
+        for creator in &["SHORT", "ÄBCDEFG", "ECHO ECH"] {
+            match a.encrypt_group_text_msg(creator, &[0; 8], "Hi", &b_pub) {
+                Err(CryptoError::BadMessage(_)) => {}
+                other => panic!("Unexpected result for {:?}: {:?}", creator, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_encrypt_ballot_create_msg() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let ballot = BallotMessage::builder("Lunch?")
+            .ballot_id([1, 2, 3, 4, 5, 6, 7, 8])
+            .choices(vec!["Pizza", "Sushi"])
+            .build()
+            .unwrap();
+        let encrypted = a.encrypt_ballot_create_msg(&ballot, &b_pub);
+        match b.decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub) {
+            Ok(DecryptedMessage::Other { msgtype, data }) => {
+                assert_eq!(msgtype, u8::from(MessageType::BallotCreate));
+                assert_eq!(&data[..8], &[1, 2, 3, 4, 5, 6, 7, 8]);
+                let json: json::Value = json::from_slice(&data[8..]).unwrap();
+                assert_eq!(json["d"], "Lunch?");
+                assert_eq!(json["c"][1]["n"], "Sushi");
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_raw_with_nonce() {
+        let (a_pub, a_sec) = backend::gen_keypair();
+        let (b_pub, b_sec) = backend::gen_keypair();
+        let nonce = [42; 24];
+        let first = encrypt_raw_with_nonce(b"data", &nonce, &b_pub, &a_sec);
+        let second = encrypt_raw_with_nonce(b"data", &nonce, &b_pub, &a_sec);
+        assert_eq!(first.nonce, nonce);
+        assert_eq!(first.ciphertext, second.ciphertext);
+        let decrypted = decrypt_raw(&first.ciphertext, &nonce, &a_pub, &b_sec);
+        assert_eq!(decrypted.unwrap(), b"data");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_encrypted_message_serde() {
+        let msg = EncryptedMessage {
+            ciphertext: vec![0xde, 0xad, 0xbe, 0xef],
+            nonce: [1; 24],
+            msg_type: Some(MessageType::Text),
+        };
+        let serialized = json::to_string(&msg).unwrap();
+        assert_eq!(
+            serialized,
+            "{\"ciphertext\":\"deadbeef\",\"nonce\":\"010101010101010101010101010101010101010101010101\"}"
+        );
+        let deserialized: EncryptedMessage = json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.ciphertext, msg.ciphertext);
+        assert_eq!(deserialized.nonce, msg.nonce);
+        assert_eq!(deserialized.msg_type, None);
+        assert!(json::from_str::<EncryptedMessage>(
+            "{\"ciphertext\":\"deadbeef\",\"nonce\":\"0101\"}"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_generate_keypair() {
+        let (public_key, private_key) = generate_keypair();
+        assert_eq!(private_key.public_key(), public_key);
+        let (other_public_key, _) = generate_keypair();
+        assert_ne!(public_key, other_public_key);
+    }
+
+    #[test]
+    fn test_generate_keypair_hex() {
+        let (public_key, private_key) = generate_keypair_hex();
+        assert_eq!(public_key.len(), 64);
+        assert_eq!(private_key.len(), 64);
+        let private_key = SecretKey::from_slice(&HEXLOWER.decode(private_key.as_bytes()).unwrap());
+        assert_eq!(
+            private_key.unwrap().public_key(),
+            public_key.parse::<RecipientKey>().unwrap().0
+        );
+    }
+
+    #[test]
+    fn test_decrypt_delivery_receipt() {
+        use crate::types::{MessageId, ReceiptStatus};
+
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let receipt = DeliveryReceipt::new(
+            ReceiptStatus::UserAcknowledged,
+            vec![MessageId::new([1; 8]), MessageId::new([2; 8])],
+        );
+        let encrypted = a.encrypt_delivery_receipt(&receipt, &b_pub);
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(decrypted, DecryptedMessage::DeliveryReceipt(receipt));
+    }
+
+    #[test]
+    fn test_encrypted_message_msg_type() {
+        let ((a, _), (_, b_pub)) = api_pair();
+        assert_eq!(a.encrypt_raw(b"data", &b_pub).msg_type, None);
+        assert_eq!(
+            a.encrypt_text_msg("Hi", &b_pub).msg_type,
+            Some(MessageType::Text)
+        );
+        let location = a
+            .encrypt_location_msg(0.0, 0.0, None, None, &b_pub)
+            .unwrap();
+        assert_eq!(location.msg_type, Some(MessageType::Location));
+    }
+
+    #[test]
+    fn test_decrypt_wrong_key() {
+        let ((a, _), (b, b_pub)) = api_pair();
+        let encrypted = a.encrypt_text_msg("Hello", &b_pub);
+        match b.decrypt(&encrypted.ciphertext, &encrypted.nonce, &b_pub) {
+            Err(CryptoError::DecryptionFailed) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decrypt_empty() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let encrypted = a.encrypt_raw(&[], &b_pub);
+        match b.decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub) {
+            Err(CryptoError::EmptyMessage) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decrypt_bad_padding() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        for plaintext in &[
+            &[0x01, b'a', 0][..],
+            &[0x01, b'a', 3, 3][..],
+            &[0x01, b'a', 2, 3, 3][..],
+            &[3, 3][..],
+        ] {
+            let encrypted = a.encrypt_raw(plaintext, &b_pub);
+            match b.decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub) {
+                Err(CryptoError::BadPadding) => {}
+                other => panic!("Unexpected result for {:?}: {:?}", plaintext, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_decrypt_unknown_type() {
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let encrypted = a.encrypt_raw(&[0xfe, 1, 2, 3, 1], &b_pub);
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(
+            decrypted,
+            DecryptedMessage::Other {
+                msgtype: 0xfe,
+                data: vec![1, 2, 3],
+            }
+        );
     }
 
     #[test]
@@ -259,22 +1783,41 @@ mod test {
     #[test]
     fn test_recipient_key_from_str() {
         let encoded = "5cf143cd8f3652f31d9b44786c323fbc222ecfcbb8dac5caf5caa257ac272df0";
-        let recipient = RecipientKey::from_str(&encoded);
+        let recipient = RecipientKey::from_str(encoded);
         assert!(recipient.is_ok());
 
         let encoded = "5CF143CD8F3652F31D9B44786C323FBC222ECFCBB8DAC5CAF5CAA257AC272DF0";
-        let recipient = RecipientKey::from_str(&encoded);
+        let recipient = RecipientKey::from_str(encoded);
         assert!(recipient.is_ok());
 
         let too_short = "5cf143cd8f3652f31d9b44786c323fbc222ecfcbb8dac5ca";
-        let recipient = RecipientKey::from_str(&too_short);
+        let recipient = RecipientKey::from_str(too_short);
         assert!(recipient.is_err());
 
         let invalid = "qyz143cd8f3652f31d9b44786c323fbc222ecfcbb8dac5caf5caa257ac272df0";
-        let recipient = RecipientKey::from_str(&invalid);
+        let recipient = RecipientKey::from_str(invalid);
         assert!(recipient.is_err());
     }
 
+    #[test]
+    fn test_recipient_key_from_base64() {
+        let hex = "5cf143cd8f3652f31d9b44786c323fbc222ecfcbb8dac5caf5caa257ac272df0";
+        let expected = RecipientKey::from_str(hex).unwrap();
+        for encoded in &[
+            "XPFDzY82UvMdm0R4bDI/vCIuz8u42sXK9cqiV6wnLfA=",
+            "XPFDzY82UvMdm0R4bDI/vCIuz8u42sXK9cqiV6wnLfA",
+        ] {
+            assert_eq!(RecipientKey::from_base64(encoded).unwrap().0, expected.0);
+            assert_eq!(RecipientKey::from_str(encoded).unwrap().0, expected.0);
+        }
+
+        match RecipientKey::from_base64("XPFDzY82UvMdm0R4bDI/vCIuz8u42sXK") {
+            Err(CryptoError::BadKey(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(RecipientKey::from_base64("not base64!").is_err());
+    }
+
     #[test]
     fn test_recipient_key_as_bytes() {
         let bytes = [0; 32];
@@ -285,6 +1828,19 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_recipient_key_eq_hash() {
+        use std::collections::HashSet;
+
+        let a = RecipientKey::from([1; 32]);
+        let b = RecipientKey::from([2; 32]);
+        assert_eq!(a, RecipientKey::from([1; 32]));
+        assert_ne!(a, b);
+
+        let keys: HashSet<RecipientKey> = vec![a.clone(), b, a].into_iter().collect();
+        assert_eq!(keys.len(), 2);
+    }
+
     #[test]
     fn test_recipient_key_as_string() {
         let mut bytes = [0; 32];
@@ -297,4 +1853,99 @@ mod test {
             "ff000000000000000000000000000000000000000000000000000000000000ee"
         );
     }
+
+    #[test]
+    fn test_recipient_key_from_qr_payload() {
+        let (id, key) = RecipientKey::from_qr_payload(
+            "3mid:ECHOECHO,4a6a1b34dcef15d43cb74de2fd36091be99fbbaf126d099d47d83d919712c72b",
+        )
+        .unwrap();
+        assert_eq!(id, "ECHOECHO");
+        assert_eq!(
+            String::from(key),
+            "4a6a1b34dcef15d43cb74de2fd36091be99fbbaf126d099d47d83d919712c72b"
+        );
+
+        // Additional fields are ignored
+        let (id, _) = RecipientKey::from_qr_payload(
+            "3mid:*3MAGWID,4a6a1b34dcef15d43cb74de2fd36091be99fbbaf126d099d47d83d919712c72b,1594744526",
+        )
+        .unwrap();
+        assert_eq!(id, "*3MAGWID");
+    }
+
+    #[test]
+    fn test_recipient_key_from_qr_payload_invalid() {
+        let key = "4a6a1b34dcef15d43cb74de2fd36091be99fbbaf126d099d47d83d919712c72b";
+        for payload in &[
+            format!("ECHOECHO,{}", key),
+            format!("3mid:ECHO,{}", key),
+            "3mid:ECHOECHO".to_string(),
+            "3mid:ECHOECHO,".to_string(),
+            format!("3mid:ECHOECHO,{}", &key[..62]),
+            format!("3mid:ECHOECHO,{}", key.replace('4', "x")),
+        ] {
+            match RecipientKey::from_qr_payload(payload) {
+                Err(CryptoError::BadKey(_)) => {}
+                other => panic!("Unexpected result for {:?}: {:?}", payload, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_encrypt_image_msg_with_nonce() {
+        let (a_pub, a_sec) = backend::gen_keypair();
+        let (b_pub, b_sec) = backend::gen_keypair();
+        let blob_id = BlobId::from_str("00112233445566778899aabbccddeeff").unwrap();
+        let image_data_nonce = [1; 24];
+        let nonce = [2; 24];
+        let encrypt = |padding_amount: u8| {
+            encrypt_image_msg_with_nonce(
+                &blob_id,
+                1234,
+                &image_data_nonce,
+                &nonce,
+                NonZeroU8::new(padding_amount).unwrap(),
+                &b_pub,
+                &a_sec,
+            )
+        };
+        let first = encrypt(10);
+        let second = encrypt(10);
+        assert_eq!(first.nonce, nonce);
+        assert_eq!(first.ciphertext, second.ciphertext);
+        assert_eq!(encrypt(20).ciphertext.len(), first.ciphertext.len() + 10);
+        assert_eq!(first.msg_type, Some(MessageType::Image));
+        let decrypted = decrypt(&first.ciphertext, &nonce, &a_pub, &b_sec).unwrap();
+        assert_eq!(
+            decrypted,
+            DecryptedMessage::Image {
+                blob_id,
+                img_size_bytes: 1234,
+                image_data_nonce,
+            }
+        );
+    }
+
+    #[test]
+    fn test_encrypt_fs_control() {
+        use crate::types::{FsSessionId, FsTerminateCause};
+
+        let ((a, a_pub), (b, b_pub)) = api_pair();
+        let msg = FsControlMessage::Terminate {
+            session_id: FsSessionId::random(),
+            cause: FsTerminateCause::DisabledByLocal,
+        };
+        let encrypted = a.encrypt_fs_control(&msg, &b_pub);
+        let decrypted = b
+            .decrypt(&encrypted.ciphertext, &encrypted.nonce, &a_pub)
+            .unwrap();
+        assert_eq!(
+            decrypted,
+            DecryptedMessage::Other {
+                msgtype: 0xa0,
+                data: msg.to_bytes(),
+            }
+        );
+    }
 }
@@ -1,6 +1,7 @@
 //! Error types used in this library.
 
 use std::io::Error as IoError;
+use std::time::Duration;
 
 use quick_error::quick_error;
 use reqwest::Error as ReqwestError;
@@ -12,6 +13,11 @@ quick_error! {
         /// The recipient identity is invalid or the account is not set up for basic mode
         BadSenderOrRecipient {}
 
+        /// The account is not set up for the mode used (basic or end-to-end)
+        ModeNotEnabled {
+            display("ModeNotEnabled: The Gateway ID is not set up for this mode (basic or end-to-end)")
+        }
+
         /// API identity or secret is incorrect
         BadCredentials {}
 
@@ -24,8 +30,20 @@ quick_error! {
         /// Message is too long
         MessageTooLong {}
 
-        /// Internal server error
-        ServerError {}
+        /// Internal server error, with the response body (may be empty)
+        ServerError(body: String) {
+            display("ServerError: {}", body)
+        }
+
+        /// Unexpected HTTP status code, with the response body (may be empty)
+        HttpStatus { code: u16, body: String } {
+            display("HttpStatus: {}: {}", code, body)
+        }
+
+        /// Too many requests, retry after the specified duration
+        RateLimited { retry_after: Duration } {
+            display("RateLimited: retry after {:?}", retry_after)
+        }
 
         /// Wrong hash length
         BadHashLength {}
@@ -36,16 +54,32 @@ quick_error! {
         /// Invalid blob ID
         BadBlobId {}
 
+        /// The MAC of an incoming message is invalid
+        InvalidMac {}
+
+        /// The blob does not exist (or has already been deleted)
+        BlobNotFound {}
+
+        /// The blob has expired
+        BlobExpired {}
+
+        /// The request timed out (see `ApiBuilder::with_timeout`)
+        Timeout(err: ReqwestError) {
+            display("Timeout: {}", err)
+            source(err)
+        }
+
         /// Error when sending request (via reqwest)
         RequestError(err: ReqwestError) {
-            from()
             display("RequestError: {}", err)
+            source(err)
         }
 
         /// Error when reading response
         IoError(err: IoError) {
             from()
             display("IoError: {}", err)
+            source(err)
         }
 
         /// Error while parsing response
@@ -68,6 +102,28 @@ quick_error! {
         BadKey(msg: String) {
             from()
         }
+        /// Decryption failed (wrong key or tampered ciphertext)
+        DecryptionFailed {}
+        /// The decrypted message is empty
+        EmptyMessage {}
+        /// The padding of the decrypted message is invalid
+        BadPadding {}
+        /// The message payload is malformed
+        BadMessage(msg: String) {
+            display("BadMessage: {}", msg)
+        }
+    }
+}
+
+impl From<ReqwestError> for ApiError {
+    /// Map timeouts to [`ApiError::Timeout`], all other errors to
+    /// [`ApiError::RequestError`].
+    fn from(err: ReqwestError) -> Self {
+        if err.is_timeout() {
+            ApiError::Timeout(err)
+        } else {
+            ApiError::RequestError(err)
+        }
     }
 }
 
@@ -79,6 +135,20 @@ quick_error! {
         MissingKey {}
         /// Invalid libsodium private key.
         InvalidKey(msg: String) {}
+        /// Invalid Gateway ID.
+        InvalidId(msg: String) {}
+        /// Invalid proxy URL.
+        InvalidProxy(msg: String) {}
+        /// Invalid User-Agent header value.
+        InvalidUserAgent(msg: String) {}
+        /// Malformed Threema ID backup.
+        InvalidBackup(msg: String) {}
+        /// The Threema ID backup could not be decrypted with the password.
+        WrongBackupPassword {}
+        /// A required environment variable is not set.
+        MissingEnvVar(name: &'static str) {
+            display("MissingEnvVar: {} is not set", name)
+        }
     }
 }
 
@@ -92,3 +162,41 @@ quick_error! {
         }
     }
 }
+
+quick_error! {
+    /// Errors when interacting with the [`BallotMessageBuilder`](../struct.BallotMessageBuilder.html).
+    #[derive(Debug)]
+    pub enum BallotMessageBuilderError {
+        /// The ballot is incomplete or invalid (e.g. it has no choices).
+        Invalid(msg: &'static str) {
+            display("Invalid: {}", msg)
+        }
+    }
+}
+
+quick_error! {
+    /// Errors when interacting with the [`ImageMessageBuilder`](../struct.ImageMessageBuilder.html).
+    #[derive(Debug)]
+    pub enum ImageMessageBuilderError {
+        /// A required field has not been set.
+        MissingField(name: &'static str) {
+            display("MissingField: {}", name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn test_api_error_source() {
+        let err = ApiError::from(io::Error::other("broken pipe"));
+        let source = err.source().expect("No source");
+        assert_eq!(source.to_string(), "broken pipe");
+        assert!(ApiError::NoCredits.source().is_none());
+    }
+}
@@ -12,6 +12,7 @@
 //! ## Example: Send simple (transport encrypted) message
 //!
 //! ```no_run
+//! # #[cfg(feature = "blocking")] {
 //! use threema_gateway::{ApiBuilder, Recipient};
 //!
 //! let from = "*YOUR_ID";
@@ -25,11 +26,13 @@
 //!     Ok(msg_id) => println!("Sent. Message id is {}.", msg_id),
 //!     Err(e) => println!("Could not send message: {:?}", e),
 //! }
+//! # }
 //! ```
 //!
 //! ## Example: Send end-to-end encrypted message
 //!
 //! ```no_run
+//! # #[cfg(feature = "blocking")] {
 //! use threema_gateway::{ApiBuilder, RecipientKey};
 //!
 //! let from = "*YOUR_ID";
@@ -45,11 +48,10 @@
 //!     .unwrap();
 //!
 //! // Fetch public key
-//! // Note: In a real application, you should cache the public key
-//! let public_key = api.lookup_pubkey(to).unwrap();
+//! // Note: The public key is cached, subsequent lookups will not query the API
+//! let recipient_key: RecipientKey = api.lookup_pubkey_cached(to).unwrap();
 //!
 //! // Encrypt
-//! let recipient_key: RecipientKey = public_key.parse().unwrap();
 //! let encrypted = api.encrypt_text_msg(text, &recipient_key);
 //!
 //! // Send
@@ -57,8 +59,66 @@
 //!     Ok(msg_id) => println!("Sent. Message id is {}.", msg_id),
 //!     Err(e) => println!("Could not send message: {:?}", e),
 //! }
+//! # }
+//! ```
+//!
+//! ## Example: Async API
+//!
+//! All API objects are also available in an async variant. They are created
+//! with [`ApiBuilder::into_simple_async`] and [`ApiBuilder::into_e2e_async`].
+//!
+//! ```no_run
+//! use threema_gateway::{ApiBuilder, RecipientKey};
+//!
+//! # async fn send() {
+//! let api = ApiBuilder::new("*YOUR_ID", "your-gateway-secret")
+//!     .with_private_key_str("your-private-key")
+//!     .and_then(|builder| builder.into_e2e_async())
+//!     .unwrap();
+//!
+//! let public_key = api.lookup_pubkey("ECHOECHO").await.unwrap();
+//! let recipient_key: RecipientKey = public_key.parse().unwrap();
+//! let encrypted = api.encrypt_text_msg("Very secret message!", &recipient_key);
+//! match api.send("ECHOECHO", &encrypted, false).await {
+//!     Ok(msg_id) => println!("Sent. Message id is {}.", msg_id),
+//!     Err(e) => println!("Could not send message: {:?}", e),
+//! }
+//! # }
 //! ```
 //!
+//! ## Reusing API objects
+//!
+//! Every API object holds an HTTP client with a connection pool that is
+//! reused for all requests. Cloning an API object is cheap, and all clones
+//! share the same connection pool, so prefer cloning over building new
+//! instances.
+//!
+//! ## Cargo features
+//!
+//! - `blocking` (enabled by default): Provides the blocking [`SimpleApi`] and
+//!   [`E2eApi`] types. These must not be used from within an async context.
+//! - `serde`: Implements `Serialize` and `Deserialize` for [`EncryptedMessage`],
+//!   [`BlobId`] and [`FileMessage`], so they can be persisted or passed
+//!   between processes. Byte fields are hex encoded.
+//! - `libsodium` (enabled by default): Uses libsodium (through `sodiumoxide`)
+//!   for encryption. libsodium is initialized automatically on first use, so
+//!   there is no need to call `sodiumoxide::init` manually.
+//! - `rust-crypto`: Uses the pure Rust `crypto_box` crate for encryption
+//!   instead of libsodium, e.g. to compile to WebAssembly. If both features
+//!   are enabled, the pure Rust implementation is used.
+//! - `tracing`: Wraps every network operation in a `tracing` span, carrying
+//!   the operation name and the (redacted) recipient as fields.
+//! - `server`: Provides `AsyncE2eApi::listen`, which starts an HTTP server
+//!   that receives, verifies and decrypts incoming messages and returns them
+//!   as a stream.
+//! - `test-util`: Provides an in-process mock of the Gateway API in the
+//!   [`mock`] module, to test code that uses this library offline.
+//! - `native-tls` (enabled by default): Uses the TLS implementation of the
+//!   platform (e.g. OpenSSL on Linux) for HTTPS connections.
+//! - `rustls-tls`: Uses `rustls` for HTTPS connections instead, e.g. for
+//!   static musl builds. Disable the default features to drop `native-tls`.
+//!   If both features are enabled, `rustls` is used.
+//!
 //! For more examples, see the
 //! [`examples/`](https://github.com/dbrgn/threema-gateway-rs/tree/master/examples) directory.
 
@@ -66,21 +126,47 @@
 extern crate log;
 
 mod api;
+mod backend;
+#[cfg(feature = "blocking")]
+mod blocking;
+mod cache;
 mod connection;
 mod crypto;
 pub mod errors;
 mod lookup;
+mod message_builder;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
+mod observer;
+mod receive;
+#[cfg(feature = "server")]
+mod server;
 mod types;
 
+pub use crate::backend::{Key, PrecomputedKey, PublicKey, SecretKey};
 pub use mime::Mime;
-pub use sodiumoxide::crypto::box_::{PublicKey, SecretKey};
-pub use sodiumoxide::crypto::secretbox::Key;
 
-pub use crate::api::{ApiBuilder, E2eApi, SimpleApi};
-pub use crate::connection::Recipient;
-pub use crate::crypto::{EncryptedMessage, RecipientKey};
-pub use crate::lookup::{Capabilities, LookupCriterion};
-pub use crate::types::{BlobId, FileMessage, FileMessageBuilder, MessageType, RenderingType};
+pub use crate::api::{ApiBuilder, AsyncE2eApi, AsyncSimpleApi, OnPremConfig};
+#[cfg(feature = "blocking")]
+pub use crate::blocking::{E2eApi, SimpleApi};
+pub use crate::connection::{split_text, MessageFlags, Recipient, SendOptions, MAX_TEXT_LENGTH};
+pub use crate::crypto::{
+    decrypt_blob_data, generate_keypair, generate_keypair_hex, DecryptedMessage, EncryptedMessage,
+    RecipientKey,
+};
+pub use crate::lookup::{Capabilities, CapabilityFlags, LookupCriterion};
+pub use crate::message_builder::{MessageBuilder, NoBlob, WithBlob};
+pub use crate::observer::{ApiObserver, ApiOperation};
+pub use crate::receive::{parse_incoming, IncomingMessage};
+#[cfg(feature = "server")]
+pub use crate::server::IncomingStream;
+pub use crate::types::{
+    cost_of, BallotAssessment, BallotMessage, BallotMessageBuilder, BallotType, BlobId,
+    BlobUploadResult, Credits, DeliveryReceipt, DistributionList, FileMessage, FileMessageBuilder,
+    FsControlMessage, FsRejectCause, FsSessionId, FsTerminateCause, ImageMessage,
+    ImageMessageBuilder, MessageId, MessageType, ReceiptStatus, RenderingType, COST_PER_BLOB,
+    COST_PER_MESSAGE,
+};
 
 const MSGAPI_URL: &str = "https://msgapi.threema.ch";
 
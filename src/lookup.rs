@@ -1,13 +1,23 @@
 //! ID and public key lookups.
 
+use std::collections::HashMap;
 use std::fmt;
-use std::io::Read;
 use std::str;
 
-use reqwest::Client;
-
-use crate::connection::map_response_code;
+use bitflags::bitflags;
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::{self as json, json};
+use sha2::Sha256;
+
+#[cfg(feature = "tracing")]
+use crate::connection::redact;
+use crate::connection::{check_response, redact_url};
 use crate::errors::ApiError;
+use crate::types::Credits;
 
 /// Different ways to look up a Threema ID in the directory.
 #[derive(Debug, PartialEq)]
@@ -17,17 +27,94 @@ pub enum LookupCriterion {
     /// The phone number must be passed as an HMAC-SHA256 hash of the E.164
     /// number without the leading `+`. The HMAC key is
     /// `85adf8226953f3d96cfd5d09bf29555eb955fcd8aa5ec4f9fcd869e258370723`
-    /// (in hexadecimal).
+    /// (in hexadecimal). Use [`hashed_phone`](#method.hashed_phone) to
+    /// compute the hash.
     PhoneHash(String),
     /// The email address.
     Email(String),
     /// The lowercased and whitespace-trimmed email address must be hashed with
     /// HMAC-SHA256. The HMAC key is
     /// `30a5500fed9701fa6defdb610841900febb8e430881f7ad816826264ec09bad7`
-    /// (in hexadecimal).
+    /// (in hexadecimal). Use [`hashed_email`](#method.hashed_email) to
+    /// compute the hash.
     EmailHash(String),
 }
 
+/// HMAC key used to hash phone numbers.
+const PHONE_HMAC_KEY: [u8; 32] = [
+    0x85, 0xad, 0xf8, 0x22, 0x69, 0x53, 0xf3, 0xd9, 0x6c, 0xfd, 0x5d, 0x09, 0xbf, 0x29, 0x55, 0x5e,
+    0xb9, 0x55, 0xfc, 0xd8, 0xaa, 0x5e, 0xc4, 0xf9, 0xfc, 0xd8, 0x69, 0xe2, 0x58, 0x37, 0x07, 0x23,
+];
+
+/// HMAC key used to hash email addresses.
+const EMAIL_HMAC_KEY: [u8; 32] = [
+    0x30, 0xa5, 0x50, 0x0f, 0xed, 0x97, 0x01, 0xfa, 0x6d, 0xef, 0xdb, 0x61, 0x08, 0x41, 0x90, 0x0f,
+    0xeb, 0xb8, 0xe4, 0x30, 0x88, 0x1f, 0x7a, 0xd8, 0x16, 0x82, 0x62, 0x64, 0xec, 0x09, 0xba, 0xd7,
+];
+
+/// The HMAC keys used to hash phone numbers and email addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HmacKeys {
+    pub(crate) phone: [u8; 32],
+    pub(crate) email: [u8; 32],
+}
+
+impl Default for HmacKeys {
+    fn default() -> Self {
+        HmacKeys {
+            phone: PHONE_HMAC_KEY,
+            email: EMAIL_HMAC_KEY,
+        }
+    }
+}
+
+/// Return the hex encoded HMAC-SHA256 of `data` using the specified key.
+fn hmac_hex(key: &[u8], data: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(data.as_bytes());
+    HEXLOWER.encode(&mac.finalize().into_bytes())
+}
+
+impl LookupCriterion {
+    /// Create a [`PhoneHash`](#variant.PhoneHash) criterion from a phone
+    /// number.
+    ///
+    /// All non-digit characters (e.g. a leading `+`, spaces or dashes) are
+    /// removed before hashing, so the number should be passed in E.164
+    /// format.
+    pub fn hashed_phone(phone: &str) -> Self {
+        Self::hashed_phone_with_key(phone, &PHONE_HMAC_KEY)
+    }
+
+    /// Create a [`PhoneHash`](#variant.PhoneHash) criterion from a phone
+    /// number, using a custom HMAC key (e.g. for Threema OnPrem).
+    ///
+    /// The phone number is normalized like with
+    /// [`hashed_phone`](#method.hashed_phone).
+    pub fn hashed_phone_with_key(phone: &str, hmac_key: &[u8]) -> Self {
+        let normalized: String = phone.chars().filter(char::is_ascii_digit).collect();
+        LookupCriterion::PhoneHash(hmac_hex(hmac_key, &normalized))
+    }
+
+    /// Create an [`EmailHash`](#variant.EmailHash) criterion from an email
+    /// address.
+    ///
+    /// The address is trimmed and lowercased before hashing.
+    pub fn hashed_email(email: &str) -> Self {
+        Self::hashed_email_with_key(email, &EMAIL_HMAC_KEY)
+    }
+
+    /// Create an [`EmailHash`](#variant.EmailHash) criterion from an email
+    /// address, using a custom HMAC key (e.g. for Threema OnPrem).
+    ///
+    /// The address is normalized like with
+    /// [`hashed_email`](#method.hashed_email).
+    pub fn hashed_email_with_key(email: &str, hmac_key: &[u8]) -> Self {
+        let normalized = email.trim().to_lowercase();
+        LookupCriterion::EmailHash(hmac_hex(hmac_key, &normalized))
+    }
+}
+
 impl fmt::Display for LookupCriterion {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -39,6 +126,51 @@ impl fmt::Display for LookupCriterion {
     }
 }
 
+bitflags! {
+    /// A compact representation of the capabilities known to this library.
+    ///
+    /// Use [`Capabilities::flags`](struct.Capabilities.html#method.flags) to
+    /// convert from [`Capabilities`](struct.Capabilities.html).
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct CapabilityFlags: u32 {
+        const TEXT = 1 << 0;
+        const IMAGE = 1 << 1;
+        const VIDEO = 1 << 2;
+        const AUDIO = 1 << 3;
+        const FILE = 1 << 4;
+        const GROUP = 1 << 5;
+        const BALLOT = 1 << 6;
+        const VOICE_CALL = 1 << 7;
+        const VIDEO_CALL = 1 << 8;
+        const PFS = 1 << 9;
+    }
+}
+
+/// The capability names corresponding to the capability flags.
+const CAPABILITY_NAMES: [(CapabilityFlags, &str); 10] = [
+    (CapabilityFlags::TEXT, "text"),
+    (CapabilityFlags::IMAGE, "image"),
+    (CapabilityFlags::VIDEO, "video"),
+    (CapabilityFlags::AUDIO, "audio"),
+    (CapabilityFlags::FILE, "file"),
+    (CapabilityFlags::GROUP, "group"),
+    (CapabilityFlags::BALLOT, "ballot"),
+    (CapabilityFlags::VOICE_CALL, "call"),
+    (CapabilityFlags::VIDEO_CALL, "videocall"),
+    (CapabilityFlags::PFS, "pfs"),
+];
+
+impl CapabilityFlags {
+    /// Return the capability names of the set flags.
+    pub(crate) fn names(self) -> Vec<&'static str> {
+        CAPABILITY_NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
 /// A struct containing flags according to the capabilities of a Threema ID.
 #[derive(Debug, PartialEq)]
 pub struct Capabilities {
@@ -54,6 +186,12 @@ pub struct Capabilities {
     pub file: bool,
     /// List of other capabilities this ID has.
     pub other: Vec<String>,
+    /// List of all capabilities this ID has, lowercased and without
+    /// duplicates, in the order returned by the server.
+    ///
+    /// This includes capabilities unknown to this library, which can be
+    /// checked with [`can`](#method.can).
+    pub raw: Vec<String>,
 }
 
 impl Capabilities {
@@ -65,6 +203,7 @@ impl Capabilities {
             audio: false,
             file: false,
             other: Vec::new(),
+            raw: Vec::new(),
         }
     }
 }
@@ -74,14 +213,17 @@ impl str::FromStr for Capabilities {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut capabilities = Capabilities::new();
         for capability in s.split(',').map(str::trim).map(str::to_lowercase) {
+            if capability.is_empty() || capabilities.raw.contains(&capability) {
+                continue;
+            }
+            capabilities.raw.push(capability.clone());
             match capability.as_ref() {
                 "text" => capabilities.text = true,
                 "image" => capabilities.image = true,
                 "video" => capabilities.video = true,
                 "audio" => capabilities.audio = true,
                 "file" => capabilities.file = true,
-                _ if !capability.is_empty() => capabilities.other.push(capability),
-                _ => { /* skip empty entries */ }
+                _ => capabilities.other.push(capability),
             };
         }
         Ok(capabilities)
@@ -106,24 +248,106 @@ impl fmt::Display for Capabilities {
 
 impl Capabilities {
     /// Return whether the specified capability is present.
+    ///
+    /// The name is compared case-insensitively.
     pub fn can(&self, capability: &str) -> bool {
-        match capability {
+        let capability = capability.to_lowercase();
+        match capability.as_str() {
             "text" => self.text,
             "image" => self.image,
             "video" => self.video,
             "audio" => self.audio,
             "file" => self.file,
-            _ => self.other.contains(&capability.to_lowercase()),
+            _ => self.other.contains(&capability),
         }
     }
+
+    /// Whether the ID can receive group messages.
+    pub fn group(&self) -> bool {
+        self.can("group")
+    }
+
+    /// Whether the ID can receive ballots (polls).
+    pub fn ballot(&self) -> bool {
+        self.can("ballot")
+    }
+
+    /// Whether the ID supports voice calls.
+    pub fn voice_call(&self) -> bool {
+        self.can("call")
+    }
+
+    /// Whether the ID supports video calls.
+    pub fn video_call(&self) -> bool {
+        self.can("videocall")
+    }
+
+    /// Whether the ID supports perfect forward secrecy.
+    pub fn pfs(&self) -> bool {
+        self.can("pfs")
+    }
+
+    /// Return the known capabilities as [`CapabilityFlags`].
+    ///
+    /// Capabilities unknown to this library are not included.
+    ///
+    /// [`CapabilityFlags`]: struct.CapabilityFlags.html
+    pub fn flags(&self) -> CapabilityFlags {
+        CAPABILITY_NAMES
+            .iter()
+            .filter(|(_, name)| self.can(name))
+            .fold(CapabilityFlags::empty(), |flags, (flag, _)| flags | *flag)
+    }
+
+    /// Return the capabilities of `required` that are not present.
+    pub fn missing(&self, required: CapabilityFlags) -> CapabilityFlags {
+        required.difference(self.flags())
+    }
+
+    /// Return the known capabilities as an integer, e.g. for storing them.
+    ///
+    /// Capabilities unknown to this library are not included.
+    pub fn bits(&self) -> u32 {
+        self.flags().bits()
+    }
+
+    /// Create capabilities from an integer returned by
+    /// [`bits`](#method.bits). Unknown bits are ignored.
+    pub fn from_bits(bits: u32) -> Self {
+        CapabilityFlags::from_bits_truncate(bits)
+            .names()
+            .join(",")
+            .parse()
+            .expect("Parsing capabilities cannot fail")
+    }
+}
+
+/// Build a GET request to the specified URL, appending the additional
+/// parameters (if any) to the query string.
+fn get(
+    client: &Client,
+    url: &str,
+    additional_params: Option<&HashMap<String, String>>,
+) -> RequestBuilder {
+    let request = client.get(url);
+    match additional_params {
+        Some(params) => request.query(params),
+        None => request,
+    }
 }
 
 /// Fetch the public key for the specified Threema ID.
-pub(crate) fn lookup_pubkey(
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "lookup_pubkey", skip_all, fields(id = %redact(their_id)))
+)]
+pub(crate) async fn lookup_pubkey(
+    client: &Client,
     endpoint: &str,
     our_id: &str,
     their_id: &str,
     secret: &str,
+    additional_params: Option<&HashMap<String, String>>,
 ) -> Result<String, ApiError> {
     // Build URL
     let url = format!(
@@ -134,21 +358,23 @@ pub(crate) fn lookup_pubkey(
     debug!("Looking up public key for {}", their_id);
 
     // Send request
-    let mut res = Client::new().get(&url).send()?;
-    map_response_code(res.status(), None)?;
+    trace!("GET {}", redact_url(&url));
+    let res = get(client, &url, additional_params).send().await?;
+    let res = check_response(res, None).await?;
 
     // Read and return response body
-    let mut body = String::new();
-    res.read_to_string(&mut body)?;
-    Ok(body)
+    Ok(res.text().await?)
 }
 
 /// Look up an ID in the Threema directory.
-pub(crate) fn lookup_id(
+#[cfg_attr(feature = "tracing", tracing::instrument(name = "lookup_id", skip_all))]
+pub(crate) async fn lookup_id(
+    client: &Client,
     endpoint: &str,
     criterion: &LookupCriterion,
     our_id: &str,
     secret: &str,
+    additional_params: Option<&HashMap<String, String>>,
 ) -> Result<String, ApiError> {
     // Build URL
     let url_base = match criterion {
@@ -162,42 +388,139 @@ pub(crate) fn lookup_id(
     debug!("Looking up id key for {}", criterion);
 
     // Send request
-    let mut res = Client::new().get(&url).send()?;
-    map_response_code(res.status(), Some(ApiError::BadHashLength))?;
+    trace!("GET {}", redact_url(&url));
+    let res = get(client, &url, additional_params).send().await?;
+    let res = check_response(res, Some(ApiError::BadHashLength)).await?;
 
     // Read and return response body
-    let mut body = String::new();
-    res.read_to_string(&mut body)?;
-    Ok(body)
+    Ok(res.text().await?)
+}
+
+/// An entry of the bulk ID lookup response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkLookupEntry {
+    identity: String,
+    phone_hash: Option<String>,
+    email_hash: Option<String>,
+}
+
+/// Look up the Threema IDs for multiple criteria with a single request.
+///
+/// Plaintext criteria are hashed with the specified HMAC keys first, since
+/// the bulk endpoint only accepts hashes. The result for every criterion is
+/// returned in the same order as the criteria.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "lookup_id_bulk", skip_all, fields(count = criteria.len()))
+)]
+pub(crate) async fn lookup_id_bulk(
+    client: &Client,
+    endpoint: &str,
+    criteria: &[LookupCriterion],
+    hmac_keys: &HmacKeys,
+    our_id: &str,
+    secret: &str,
+) -> Result<Vec<Option<String>>, ApiError> {
+    let url = format!("{}/lookup/bulk?from={}&secret={}", endpoint, our_id, secret);
+
+    // Hash plaintext criteria
+    let hashed: Vec<LookupCriterion> = criteria
+        .iter()
+        .map(|criterion| match criterion {
+            LookupCriterion::Phone(ref val) => {
+                LookupCriterion::hashed_phone_with_key(val, &hmac_keys.phone)
+            }
+            LookupCriterion::Email(ref val) => {
+                LookupCriterion::hashed_email_with_key(val, &hmac_keys.email)
+            }
+            LookupCriterion::PhoneHash(ref val) => LookupCriterion::PhoneHash(val.to_lowercase()),
+            LookupCriterion::EmailHash(ref val) => LookupCriterion::EmailHash(val.to_lowercase()),
+        })
+        .collect();
+    let hashes = |phone: bool| -> Vec<&str> {
+        hashed
+            .iter()
+            .filter_map(|criterion| match (criterion, phone) {
+                (LookupCriterion::PhoneHash(ref val), true) => Some(val.as_str()),
+                (LookupCriterion::EmailHash(ref val), false) => Some(val.as_str()),
+                _ => None,
+            })
+            .collect()
+    };
+    let body = json!({
+        "phoneHashes": hashes(true),
+        "emailHashes": hashes(false),
+    });
+
+    debug!("Looking up {} ids in bulk", criteria.len());
+
+    // Send request
+    trace!("POST {}", redact_url(&url));
+    let res = client
+        .post(&url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await?;
+    let res = check_response(res, Some(ApiError::BadHashLength)).await?;
+
+    // Parse response body and map the IDs back to the criteria
+    let entries: Vec<BulkLookupEntry> = json::from_str(&res.text().await?).map_err(|e| {
+        ApiError::ParseError(format!("Could not parse bulk lookup response: {}", e))
+    })?;
+    Ok(hashed
+        .iter()
+        .map(|criterion| {
+            entries
+                .iter()
+                .find(|entry| match criterion {
+                    LookupCriterion::PhoneHash(ref val) => entry.phone_hash.as_ref() == Some(val),
+                    LookupCriterion::EmailHash(ref val) => entry.email_hash.as_ref() == Some(val),
+                    _ => false,
+                })
+                .map(|entry| entry.identity.clone())
+        })
+        .collect())
 }
 
 /// Look up remaining gateway credits.
-pub(crate) fn lookup_credits(endpoint: &str, our_id: &str, secret: &str) -> Result<i64, ApiError> {
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "lookup_credits", skip_all)
+)]
+pub(crate) async fn lookup_credits(
+    client: &Client,
+    endpoint: &str,
+    our_id: &str,
+    secret: &str,
+    additional_params: Option<&HashMap<String, String>>,
+) -> Result<Credits, ApiError> {
     let url = format!("{}/credits?from={}&secret={}", endpoint, our_id, secret);
 
     debug!("Looking up remaining credits");
 
     // Send request
-    let mut res = Client::new().get(&url).send()?;
-    map_response_code(res.status(), None)?;
+    trace!("GET {}", redact_url(&url));
+    let res = get(client, &url, additional_params).send().await?;
+    let res = check_response(res, None).await?;
 
     // Read, parse and return response body
-    let mut body = String::new();
-    res.read_to_string(&mut body)?;
-    body.trim().parse::<i64>().map_err(|_| {
-        ApiError::ParseError(format!(
-            "Could not parse response body as i64: \"{}\"",
-            body
-        ))
-    })
+    res.text().await?.parse()
 }
 
 /// Look up ID capabilities.
-pub(crate) fn lookup_capabilities(
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "lookup_capabilities", skip_all, fields(id = %redact(their_id)))
+)]
+pub(crate) async fn lookup_capabilities(
+    client: &Client,
     endpoint: &str,
     our_id: &str,
     their_id: &str,
     secret: &str,
+    additional_params: Option<&HashMap<String, String>>,
 ) -> Result<Capabilities, ApiError> {
     // Build URL
     let url = format!(
@@ -208,12 +531,12 @@ pub(crate) fn lookup_capabilities(
     debug!("Looking up capabilities for {}", their_id);
 
     // Send request
-    let mut res = Client::new().get(&url).send()?;
-    map_response_code(res.status(), Some(ApiError::BadHashLength))?;
+    trace!("GET {}", redact_url(&url));
+    let res = get(client, &url, additional_params).send().await?;
+    let res = check_response(res, Some(ApiError::BadHashLength)).await?;
 
     // Read response body
-    let mut body = String::new();
-    res.read_to_string(&mut body)?;
+    let body = res.text().await?;
 
     // Parse response body
     body.parse()
@@ -221,7 +544,7 @@ pub(crate) fn lookup_capabilities(
 
 #[cfg(test)]
 mod tests {
-    use super::{Capabilities, LookupCriterion};
+    use super::{Capabilities, CapabilityFlags, LookupCriterion};
 
     #[test]
     fn test_lookup_criterion_display() {
@@ -235,6 +558,32 @@ mod tests {
         assert_eq!(&email_hash.to_string(), "email hash 1234567890abcdef");
     }
 
+    #[test]
+    fn test_hashed_phone() {
+        let expected = "ad398f4d7ebe63c6550a486cc6e07f9baa09bd9d8b3d8cb9d9be106d35a7fdbc";
+        assert_eq!(
+            LookupCriterion::hashed_phone("41791234567"),
+            LookupCriterion::PhoneHash(expected.into())
+        );
+        assert_eq!(
+            LookupCriterion::hashed_phone("+41 79 123 45 67"),
+            LookupCriterion::PhoneHash(expected.into())
+        );
+    }
+
+    #[test]
+    fn test_hashed_email() {
+        let expected = "1ea093239cc5f0e1b6ec81b866265b921f26dc4033025410063309f4d1a8ee2c";
+        assert_eq!(
+            LookupCriterion::hashed_email("test@threema.ch"),
+            LookupCriterion::EmailHash(expected.into())
+        );
+        assert_eq!(
+            LookupCriterion::hashed_email("  Test@Threema.ch \n"),
+            LookupCriterion::EmailHash(expected.into())
+        );
+    }
+
     #[test]
     fn test_parse_capabilities_empty() {
         assert_eq!(
@@ -246,6 +595,7 @@ mod tests {
                 audio: false,
                 file: false,
                 other: vec![],
+                raw: vec![],
             }
         );
     }
@@ -261,6 +611,7 @@ mod tests {
                 audio: false,
                 file: false,
                 other: vec![],
+                raw: vec!["image".into()],
             }
         );
     }
@@ -276,6 +627,7 @@ mod tests {
                 audio: false,
                 file: true,
                 other: vec![],
+                raw: vec!["image".into(), "video".into(), "file".into()],
             }
         );
     }
@@ -291,6 +643,7 @@ mod tests {
                 audio: false,
                 file: false,
                 other: vec!["jetpack".into(), "lasersword".into()],
+                raw: vec!["jetpack".into(), "text".into(), "lasersword".into()],
             }
         );
     }
@@ -308,6 +661,12 @@ mod tests {
                 audio: false,
                 file: false,
                 other: vec!["jetpack".into(), "lasersword".into(), ".".into()],
+                raw: vec![
+                    "jetpack".into(),
+                    "text".into(),
+                    "lasersword".into(),
+                    ".".into()
+                ],
             }
         );
     }
@@ -326,12 +685,61 @@ mod tests {
                 audio: false,
                 file: false,
                 other: vec!["jetpack".into(), "lasersword".into(), ".".into()],
+                raw: vec![
+                    "jetpack".into(),
+                    "text".into(),
+                    "lasersword".into(),
+                    ".".into()
+                ],
             }
         );
         assert!(cap.can("jetpack"));
         assert!(cap.can("text"));
+        assert!(cap.can("Text"));
         assert!(cap.can("lasersword"));
+        assert!(cap.can("LaserSword"));
         assert!(cap.can("."));
         assert!(!cap.can("image"));
     }
+
+    #[test]
+    fn test_parse_capabilities_typed() {
+        let cap = "text,group,ballot,call,videocall,pfs,text"
+            .parse::<Capabilities>()
+            .unwrap();
+        assert!(cap.text);
+        assert!(cap.group());
+        assert!(cap.ballot());
+        assert!(cap.voice_call());
+        assert!(cap.video_call());
+        assert!(cap.pfs());
+        assert_eq!(
+            cap.raw,
+            vec!["text", "group", "ballot", "call", "videocall", "pfs"]
+        );
+
+        let cap = "text".parse::<Capabilities>().unwrap();
+        assert!(!cap.group());
+        assert!(!cap.voice_call());
+    }
+
+    #[test]
+    fn test_capabilities_bits() {
+        let capabilities: Capabilities = "text,image,call,pfs,unknown".parse().unwrap();
+        let flags = capabilities.flags();
+        assert_eq!(
+            flags,
+            CapabilityFlags::TEXT
+                | CapabilityFlags::IMAGE
+                | CapabilityFlags::VOICE_CALL
+                | CapabilityFlags::PFS
+        );
+        assert_eq!(capabilities.bits(), flags.bits());
+
+        let restored = Capabilities::from_bits(capabilities.bits() | 1 << 31);
+        assert!(restored.text && restored.image && !restored.video);
+        assert!(restored.voice_call() && restored.pfs() && !restored.can("unknown"));
+        assert_eq!(restored.bits(), capabilities.bits());
+        assert_eq!(Capabilities::from_bits(0).bits(), 0);
+    }
 }
@@ -0,0 +1,244 @@
+//! A builder for end-to-end encrypted messages.
+
+use crate::crypto::{
+    encrypt, encrypt_audio_msg, encrypt_delivery_receipt, encrypt_file_msg,
+    encrypt_group_image_msg, encrypt_group_set_photo_msg, encrypt_group_text_msg,
+    encrypt_image_msg, encrypt_location_msg, EncryptedMessage, RecipientKey,
+};
+use crate::errors::CryptoError;
+use crate::types::{BlobId, DeliveryReceipt, FileMessage, MessageType};
+use crate::{Key, SecretKey};
+
+/// State of a [`MessageBuilder`] without a blob.
+///
+/// [`MessageBuilder`]: struct.MessageBuilder.html
+#[derive(Debug)]
+pub struct NoBlob;
+
+/// State of a [`MessageBuilder`] with a blob, which is required to build
+/// media messages.
+///
+/// [`MessageBuilder`]: struct.MessageBuilder.html
+#[derive(Debug)]
+pub struct WithBlob {
+    blob_id: BlobId,
+    size_bytes: u32,
+}
+
+/// Builder for end-to-end encrypted messages to a single recipient.
+///
+/// Media messages (e.g. images) can only be built after the blob ID of the
+/// uploaded media data has been set with [`blob`](#method.blob). This is
+/// checked at compile time:
+///
+/// ```compile_fail
+/// # use threema_gateway::{MessageBuilder, RecipientKey, SecretKey};
+/// # fn f(recipient_key: &RecipientKey, private_key: &SecretKey) {
+/// let builder = MessageBuilder::new(recipient_key, private_key);
+/// builder.build_image(&[0; 24]);
+/// # }
+/// ```
+///
+/// The messages are encrypted with the functions that also back the
+/// `encrypt_*` methods of [`E2eApi`](struct.E2eApi.html). A builder is usually
+/// created with `E2eApi::message_builder`.
+#[derive(Debug)]
+pub struct MessageBuilder<'a, B = NoBlob> {
+    recipient_key: &'a RecipientKey,
+    private_key: &'a SecretKey,
+    blob: B,
+}
+
+impl<'a> MessageBuilder<'a, NoBlob> {
+    /// Create a new builder for messages from the owner of `private_key` to
+    /// the owner of `recipient_key`.
+    pub fn new(recipient_key: &'a RecipientKey, private_key: &'a SecretKey) -> Self {
+        MessageBuilder {
+            recipient_key,
+            private_key,
+            blob: NoBlob,
+        }
+    }
+
+    /// Set the blob ID and the size in bytes of the uploaded media data.
+    ///
+    /// Note that the size is only used for download size displaying purposes
+    /// and has no security implications.
+    pub fn blob(self, blob_id: BlobId, size_bytes: u32) -> MessageBuilder<'a, WithBlob> {
+        MessageBuilder {
+            recipient_key: self.recipient_key,
+            private_key: self.private_key,
+            blob: WithBlob {
+                blob_id,
+                size_bytes,
+            },
+        }
+    }
+
+    /// Build a text message.
+    pub fn build_text(&self, text: &str) -> EncryptedMessage {
+        encrypt(
+            text.as_bytes(),
+            MessageType::Text,
+            &self.recipient_key.0,
+            self.private_key,
+        )
+    }
+
+    /// Build a group text message.
+    ///
+    /// See [`E2eApi::encrypt_group_text_msg`](struct.E2eApi.html#method.encrypt_group_text_msg).
+    pub fn build_group_text(
+        &self,
+        group_creator: &str,
+        group_id: &[u8; 8],
+        text: &str,
+    ) -> Result<EncryptedMessage, CryptoError> {
+        encrypt_group_text_msg(
+            group_creator,
+            group_id,
+            text,
+            &self.recipient_key.0,
+            self.private_key,
+        )
+    }
+
+    /// Build a location message.
+    ///
+    /// See [`E2eApi::encrypt_location_msg`](struct.E2eApi.html#method.encrypt_location_msg).
+    pub fn build_location(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        accuracy: Option<f64>,
+        name: Option<&str>,
+    ) -> Result<EncryptedMessage, CryptoError> {
+        encrypt_location_msg(
+            latitude,
+            longitude,
+            accuracy,
+            name,
+            &self.recipient_key.0,
+            self.private_key,
+        )
+    }
+
+    /// Build a file message.
+    ///
+    /// The [`FileMessage`](struct.FileMessage.html) already references its
+    /// blobs, so no blob needs to be set on the builder.
+    pub fn build_file(&self, msg: &FileMessage) -> EncryptedMessage {
+        encrypt_file_msg(msg, &self.recipient_key.0, self.private_key)
+    }
+
+    /// Build a delivery receipt.
+    pub fn build_delivery_receipt(&self, receipt: &DeliveryReceipt) -> EncryptedMessage {
+        encrypt_delivery_receipt(receipt, &self.recipient_key.0, self.private_key)
+    }
+}
+
+impl MessageBuilder<'_, WithBlob> {
+    /// Build an image message.
+    ///
+    /// The image data must be encrypted for the recipient with
+    /// `image_data_nonce`, see
+    /// [`E2eApi::encrypt_image_msg`](struct.E2eApi.html#method.encrypt_image_msg).
+    pub fn build_image(&self, image_data_nonce: &[u8; 24]) -> EncryptedMessage {
+        encrypt_image_msg(
+            &self.blob.blob_id,
+            self.blob.size_bytes,
+            image_data_nonce,
+            &self.recipient_key.0,
+            self.private_key,
+        )
+    }
+
+    /// Build an audio message.
+    ///
+    /// See [`E2eApi::encrypt_audio_msg`](struct.E2eApi.html#method.encrypt_audio_msg).
+    pub fn build_audio(
+        &self,
+        duration_seconds: u16,
+        blob_encryption_key: &Key,
+    ) -> EncryptedMessage {
+        encrypt_audio_msg(
+            &self.blob.blob_id,
+            duration_seconds,
+            self.blob.size_bytes,
+            blob_encryption_key,
+            &self.recipient_key.0,
+            self.private_key,
+        )
+    }
+
+    /// Build a group image message.
+    ///
+    /// See [`E2eApi::encrypt_group_image_msg`](struct.E2eApi.html#method.encrypt_group_image_msg).
+    pub fn build_group_image(
+        &self,
+        group_creator: &str,
+        group_id: &[u8; 8],
+        blob_encryption_key: &Key,
+    ) -> Result<EncryptedMessage, CryptoError> {
+        encrypt_group_image_msg(
+            group_creator,
+            group_id,
+            &self.blob.blob_id,
+            self.blob.size_bytes,
+            blob_encryption_key,
+            &self.recipient_key.0,
+            self.private_key,
+        )
+    }
+
+    /// Build a message that sets the photo of a group.
+    ///
+    /// See [`E2eApi::encrypt_group_set_photo_msg`](struct.E2eApi.html#method.encrypt_group_set_photo_msg).
+    pub fn build_group_set_photo(
+        &self,
+        group_id: &[u8; 8],
+        blob_encryption_key: &Key,
+    ) -> EncryptedMessage {
+        encrypt_group_set_photo_msg(
+            group_id,
+            &self.blob.blob_id,
+            self.blob.size_bytes,
+            blob_encryption_key,
+            &self.recipient_key.0,
+            self.private_key,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend;
+    use crate::crypto::{decrypt, DecryptedMessage};
+
+    use super::*;
+
+    #[test]
+    fn test_build_text_and_image() {
+        let (own_public_key, own_private_key) = backend::gen_keypair();
+        let (public_key, private_key) = backend::gen_keypair();
+        let recipient_key = RecipientKey(public_key);
+        let builder = MessageBuilder::new(&recipient_key, &own_private_key);
+
+        let msg = builder.build_text("Hello");
+        let decrypted = decrypt(&msg.ciphertext, &msg.nonce, &own_public_key, &private_key);
+        assert_eq!(decrypted.unwrap(), DecryptedMessage::Text("Hello".into()));
+
+        let blob_id = BlobId::new([1; 16]);
+        let msg = builder.blob(blob_id.clone(), 1234).build_image(&[2; 24]);
+        assert_eq!(msg.msg_type, Some(MessageType::Image));
+        let decrypted = decrypt(&msg.ciphertext, &msg.nonce, &own_public_key, &private_key);
+        assert_eq!(
+            decrypted.unwrap(),
+            DecryptedMessage::Image {
+                blob_id,
+                img_size_bytes: 1234,
+                image_data_nonce: [2; 24],
+            }
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! An in-process mock of the Threema Gateway API for offline testing.
+//!
+//! This module is only available with the `test-util` feature.
+//!
+//! The [`MockServer`](struct.MockServer.html) listens on a local port and
+//! answers the most important API endpoints with canned responses. All
+//! requests are recorded, so tests can assert that the expected payloads
+//! were sent.
+//!
+//! ```
+//! use threema_gateway::mock::MockServer;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let server = MockServer::start().unwrap();
+//! let (public_key, _) = threema_gateway::generate_keypair();
+//! server.add_pubkey("ECHOECHO", &public_key.into());
+//!
+//! let api = server.e2e_api_async();
+//! let recipient_key = api.lookup_pubkey_cached("ECHOECHO").await.unwrap();
+//! let encrypted = api.encrypt_text_msg("Hello", &recipient_key);
+//! api.send("ECHOECHO", &encrypted, false).await.unwrap();
+//!
+//! let sent = server.sent_messages();
+//! assert_eq!(sent.len(), 1);
+//! assert_eq!(sent[0].params["to"], "ECHOECHO");
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use data_encoding::HEXLOWER;
+use serde_json as json;
+
+use crate::api::{ApiBuilder, AsyncE2eApi};
+use crate::crypto::{generate_keypair, RecipientKey};
+use crate::lookup::LookupCriterion;
+use crate::types::BlobId;
+#[cfg(feature = "blocking")]
+use crate::E2eApi;
+
+/// The Gateway ID used by the API objects created by the mock server.
+pub const MOCK_ID: &str = "*MOCKGW1";
+
+/// The Gateway secret used by the API objects created by the mock server.
+pub const MOCK_SECRET: &str = "mock-secret";
+
+/// A request received by the [`MockServer`](struct.MockServer.html).
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    /// The HTTP method, e.g. `POST`.
+    pub method: String,
+    /// The request path without the query string, e.g. `/send_e2e`.
+    pub path: String,
+    /// The query string parameters.
+    pub query: HashMap<String, String>,
+    /// The request headers, with lowercase names.
+    pub headers: HashMap<String, String>,
+    /// The URL encoded form parameters of the request body (empty for
+    /// other content types).
+    pub params: HashMap<String, String>,
+    /// The raw request body.
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    requests: Vec<MockRequest>,
+    pubkeys: HashMap<String, String>,
+    ids: HashMap<String, String>,
+    capabilities: HashMap<String, String>,
+    blobs: HashMap<String, Vec<u8>>,
+    credits: u64,
+    counter: u64,
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.counter += 1;
+        self.counter
+    }
+}
+
+/// A mock of the Threema Gateway API, running in a background thread.
+///
+/// The following endpoints are supported:
+///
+/// - `POST /send_e2e` and `POST /send_simple`: Return a new message ID.
+/// - `GET /pubkeys/<id>`: Return a public key added with
+///   [`add_pubkey`](#method.add_pubkey), or status 404.
+/// - `GET /lookup/<kind>/<value>`: Return a Threema ID added with
+///   [`add_id`](#method.add_id), or status 404.
+/// - `POST /lookup/bulk`: Return the Threema IDs added with
+///   [`add_id`](#method.add_id) for the requested phone and email hashes.
+/// - `GET /capabilities/<id>`: Return the capabilities set with
+///   [`set_capabilities`](#method.set_capabilities), or status 404.
+/// - `GET /credits`: Return the credits set with
+///   [`set_credits`](#method.set_credits) (100 by default).
+/// - `POST /upload_blob`: Store the uploaded blob and return a new blob ID.
+/// - `GET /blobs/<blob-id>`: Return an uploaded blob or a blob added with
+///   [`add_blob`](#method.add_blob), or status 404. `HEAD` requests are
+///   answered accordingly, without a body.
+///
+/// All other requests are answered with status 404. The server is shut down
+/// when it is dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    endpoint: String,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    /// Start a mock server on a random local port.
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let endpoint = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(State {
+            credits: 100,
+            ..Default::default()
+        }));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        if let Err(e) = handle_connection(stream, &state) {
+                            warn!("Mock server could not handle request: {}", e);
+                        }
+                    }
+                }
+            });
+        }
+        Ok(MockServer {
+            endpoint,
+            state,
+            shutdown,
+        })
+    }
+
+    /// Return the endpoint URL of the mock server.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Return an [`ApiBuilder`](../struct.ApiBuilder.html) that targets the
+    /// mock server, using [`MOCK_ID`](constant.MOCK_ID.html) and
+    /// [`MOCK_SECRET`](constant.MOCK_SECRET.html).
+    pub fn api_builder(&self) -> ApiBuilder {
+        ApiBuilder::new(MOCK_ID, MOCK_SECRET).with_custom_endpoint(self.endpoint.clone())
+    }
+
+    /// Return an async E2E API object with a random private key that targets
+    /// the mock server.
+    pub fn e2e_api_async(&self) -> AsyncE2eApi {
+        let (_, private_key) = generate_keypair();
+        self.api_builder()
+            .with_private_key(private_key)
+            .into_e2e_async()
+            .expect("Invalid mock API configuration")
+    }
+
+    /// Return a blocking E2E API object with a random private key that
+    /// targets the mock server.
+    #[cfg(feature = "blocking")]
+    pub fn e2e_api(&self) -> E2eApi {
+        let (_, private_key) = generate_keypair();
+        self.api_builder()
+            .with_private_key(private_key)
+            .into_e2e()
+            .expect("Invalid mock API configuration")
+    }
+
+    /// Add a public key that is returned by the public key lookup.
+    pub fn add_pubkey(&self, id: &str, key: &RecipientKey) {
+        let key = HEXLOWER.encode(key.as_bytes());
+        self.state().pubkeys.insert(id.to_string(), key);
+    }
+
+    /// Add a Threema ID that is returned by the ID lookup with the specified
+    /// criterion.
+    pub fn add_id(&self, criterion: &LookupCriterion, id: &str) {
+        let key = match criterion {
+            LookupCriterion::Phone(ref val) => format!("phone/{}", val),
+            LookupCriterion::PhoneHash(ref val) => format!("phone_hash/{}", val),
+            LookupCriterion::Email(ref val) => format!("email/{}", val),
+            LookupCriterion::EmailHash(ref val) => format!("email_hash/{}", val),
+        };
+        self.state().ids.insert(key, id.to_string());
+    }
+
+    /// Set the comma separated capabilities that are returned by the
+    /// capability lookup of the specified Threema ID.
+    pub fn set_capabilities(&self, id: &str, capabilities: &str) {
+        self.state()
+            .capabilities
+            .insert(id.to_string(), capabilities.to_string());
+    }
+
+    /// Add a blob that can be downloaded from the mock server.
+    pub fn add_blob(&self, blob_id: &BlobId, data: &[u8]) {
+        self.state().blobs.insert(blob_id.to_hex(), data.to_vec());
+    }
+
+    /// Set the number of credits returned by the credits lookup.
+    pub fn set_credits(&self, credits: u64) {
+        self.state().credits = credits;
+    }
+
+    /// Return all requests received so far.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state().requests.clone()
+    }
+
+    /// Return all requests to `/send_e2e` received so far.
+    pub fn sent_messages(&self) -> Vec<MockRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.path == "/send_e2e")
+            .collect()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the server thread
+        let _ = TcpStream::connect(self.endpoint.trim_start_matches("http://"));
+    }
+}
+
+/// Parse URL encoded parameters.
+fn parse_params(input: &[u8]) -> HashMap<String, String> {
+    form_urlencoded::parse(input).into_owned().collect()
+}
+
+/// Read a single request from the stream and write the response.
+fn handle_connection(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    // Request line
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    // Headers
+    let mut content_length = 0;
+    let mut form = false;
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+        match &*name {
+            "content-length" => content_length = value.parse().unwrap_or(0),
+            "content-type" => form = value.starts_with("application/x-www-form-urlencoded"),
+            _ => {}
+        }
+        headers.insert(name, value.to_string());
+    }
+
+    // Body
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    // Requests sent through a proxy use the absolute form
+    let target = match target.strip_prefix("http://") {
+        Some(rest) => rest[rest.find('/').unwrap_or(rest.len())..].to_string(),
+        None => target,
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), parse_params(query.as_bytes())),
+        None => (target.clone(), HashMap::new()),
+    };
+    let request = MockRequest {
+        method,
+        path,
+        query,
+        headers,
+        params: if form {
+            parse_params(&body)
+        } else {
+            HashMap::new()
+        },
+        body,
+    };
+
+    let head = request.method == "HEAD";
+    let (status, response_body) = {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        let response = respond(&request, &mut state);
+        state.requests.push(request);
+        response
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        status,
+        response_body.len()
+    )?;
+    if !head {
+        stream.write_all(&response_body)?;
+    }
+    stream.flush()
+}
+
+/// Return the status line and body of the response to a request.
+fn respond(request: &MockRequest, state: &mut State) -> (&'static str, Vec<u8>) {
+    let not_found = ("404 Not Found", Vec::new());
+    let segments: Vec<&str> = request.path.trim_start_matches('/').split('/').collect();
+    match (&*request.method, &segments[..]) {
+        ("POST", ["send_e2e"]) | ("POST", ["send_simple"]) => {
+            let message_id = format!("{:016x}", state.next_id());
+            ("200 OK", message_id.into_bytes())
+        }
+        ("GET", ["pubkeys", id]) => match state.pubkeys.get(*id) {
+            Some(key) => ("200 OK", key.clone().into_bytes()),
+            None => not_found,
+        },
+        ("POST", ["lookup", "bulk"]) => {
+            let body: json::Value = json::from_slice(&request.body).unwrap_or_default();
+            let hashes = |field: &str| -> Vec<String> {
+                body[field]
+                    .as_array()
+                    .map(|hashes| {
+                        hashes
+                            .iter()
+                            .filter_map(|hash| hash.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            let mut entries = Vec::new();
+            for (field, kind, key) in &[
+                ("phoneHashes", "phone_hash", "phoneHash"),
+                ("emailHashes", "email_hash", "emailHash"),
+            ] {
+                for hash in hashes(field) {
+                    if let Some(id) = state.ids.get(&format!("{}/{}", kind, hash)) {
+                        entries.push(json::json!({ "identity": id, *key: hash }));
+                    }
+                }
+            }
+            (
+                "200 OK",
+                json::Value::from(entries).to_string().into_bytes(),
+            )
+        }
+        ("GET", ["lookup", kind, value]) => match state.ids.get(&format!("{}/{}", kind, value)) {
+            Some(id) => ("200 OK", id.clone().into_bytes()),
+            None => not_found,
+        },
+        ("GET", ["capabilities", id]) => match state.capabilities.get(*id) {
+            Some(capabilities) => ("200 OK", capabilities.clone().into_bytes()),
+            None => not_found,
+        },
+        ("GET", ["credits"]) => ("200 OK", state.credits.to_string().into_bytes()),
+        ("POST", ["upload_blob"]) => {
+            let blob_id = format!("{:032x}", state.next_id());
+            let data = multipart_content(&request.body).unwrap_or_default();
+            state.blobs.insert(blob_id.clone(), data);
+            ("200 OK", blob_id.into_bytes())
+        }
+        ("GET", ["blobs", blob_id]) | ("HEAD", ["blobs", blob_id]) => {
+            match state.blobs.get(*blob_id) {
+                Some(data) => ("200 OK", data.clone()),
+                None => not_found,
+            }
+        }
+        _ => not_found,
+    }
+}
+
+/// Return the content of the first part of a multipart body.
+fn multipart_content(body: &[u8]) -> Option<Vec<u8>> {
+    let find = |haystack: &[u8], needle: &[u8]| {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    };
+    let boundary_end = find(body, b"\r\n")?;
+    let delimiter = [b"\r\n", &body[..boundary_end]].concat();
+    let start = find(body, b"\r\n\r\n")? + 4;
+    let len = find(&body[start..], &delimiter)?;
+    Some(body[start..start + len].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_server() {
+        let server = MockServer::start().unwrap();
+        let (public_key, _) = generate_keypair();
+        server.add_pubkey("ECHOECHO", &public_key.into());
+        server.set_credits(42);
+
+        let api = server.e2e_api_async();
+        let recipient_key = api.lookup_pubkey_typed("ECHOECHO").await.unwrap();
+        assert_eq!(recipient_key.0, public_key);
+        assert!(api.lookup_pubkey("UNKNOWN1").await.is_err());
+        assert_eq!(api.lookup_credits().await.unwrap().0, 42);
+
+        let encrypted = api.encrypt_text_msg("Hello", &recipient_key);
+        let message_id = api.send("ECHOECHO", &encrypted, false).await.unwrap();
+        assert_eq!(message_id.as_hex(), "0000000000000001");
+
+        let sent = server.sent_messages();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].params["from"], MOCK_ID);
+        assert_eq!(sent[0].params["to"], "ECHOECHO");
+        assert_eq!(
+            sent[0].params["box"],
+            HEXLOWER.encode(&encrypted.ciphertext)
+        );
+        assert_eq!(sent[0].params["noDeliveryReceipts"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_mock_server_blobs() {
+        let server = MockServer::start().unwrap();
+        let api = server.e2e_api_async();
+        let data = vec![0x42; 10_000];
+        let blob_id = api.blob_upload_raw(&data, false).await.unwrap();
+        assert_eq!(api.blob_download(&blob_id).await.unwrap(), data);
+
+        let other = BlobId::new([1; 16]);
+        assert!(matches!(
+            api.blob_download(&other).await,
+            Err(crate::errors::ApiError::BlobNotFound)
+        ));
+        server.add_blob(&other, b"blob");
+        assert_eq!(api.blob_download(&other).await.unwrap(), b"blob");
+
+        let upload = &server.requests()[0];
+        assert_eq!(upload.path, "/upload_blob");
+        assert_eq!(upload.query["secret"], MOCK_SECRET);
+    }
+}
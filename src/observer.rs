@@ -0,0 +1,89 @@
+//! Hooks for monitoring API requests.
+
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::errors::ApiError;
+
+/// The kind of an API operation reported to an [`ApiObserver`].
+///
+/// [`ApiObserver`]: trait.ApiObserver.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ApiOperation {
+    SendSimple,
+    SendE2e,
+    LookupPubkey,
+    LookupId,
+    LookupIdBulk,
+    LookupCapabilities,
+    LookupCredits,
+    BlobUpload,
+    BlobDownload,
+    BlobExists,
+}
+
+impl ApiOperation {
+    /// Return the name of the operation, e.g. `send_e2e`.
+    ///
+    /// The name is suitable as a metrics label.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApiOperation::SendSimple => "send_simple",
+            ApiOperation::SendE2e => "send_e2e",
+            ApiOperation::LookupPubkey => "lookup_pubkey",
+            ApiOperation::LookupId => "lookup_id",
+            ApiOperation::LookupIdBulk => "lookup_id_bulk",
+            ApiOperation::LookupCapabilities => "lookup_capabilities",
+            ApiOperation::LookupCredits => "lookup_credits",
+            ApiOperation::BlobUpload => "blob_upload",
+            ApiOperation::BlobDownload => "blob_download",
+            ApiOperation::BlobExists => "blob_exists",
+        }
+    }
+}
+
+impl fmt::Display for ApiOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An observer that is notified about the outcome of every request to the
+/// API, e.g. to collect metrics.
+///
+/// Register an observer with
+/// [`ApiBuilder::with_observer`](../struct.ApiBuilder.html#method.with_observer).
+/// The duration includes all retries. Both methods do nothing by default.
+///
+/// The methods are called on the task that runs the request, so they should
+/// return quickly.
+pub trait ApiObserver: Send + Sync {
+    /// Called when an operation has succeeded.
+    fn on_success(&self, _operation: ApiOperation, _duration: Duration) {}
+
+    /// Called when an operation has failed.
+    fn on_failure(&self, _operation: ApiOperation, _duration: Duration, _error: &ApiError) {}
+}
+
+/// Run the request and report its outcome to the observer, if any.
+pub(crate) async fn observe<T, Fut>(
+    observer: Option<&dyn ApiObserver>,
+    operation: ApiOperation,
+    request: Fut,
+) -> Result<T, ApiError>
+where
+    Fut: Future<Output = Result<T, ApiError>>,
+{
+    let observer = match observer {
+        Some(observer) => observer,
+        None => return request.await,
+    };
+    let start = Instant::now();
+    let result = request.await;
+    match result {
+        Ok(_) => observer.on_success(operation, start.elapsed()),
+        Err(ref e) => observer.on_failure(operation, start.elapsed(), e),
+    }
+    result
+}
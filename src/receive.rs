@@ -0,0 +1,257 @@
+//! Handle incoming messages.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use data_encoding::{HEXLOWER, HEXLOWER_PERMISSIVE};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::errors::ApiError;
+use crate::types::MessageId;
+
+/// An incoming message, as delivered by the Threema Gateway to your callback
+/// URL.
+///
+/// The message itself is still encrypted. Use
+/// [`E2eApi::decrypt`](struct.E2eApi.html#method.decrypt) with the public key
+/// of the sender to decrypt it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct IncomingMessage {
+    /// Sender identity (8 characters)
+    pub from: String,
+    /// Your API identity (8 characters, usually starts with '*')
+    pub to: String,
+    /// Message ID assigned by the sender
+    pub message_id: MessageId,
+    /// Message date set by the sender (UNIX timestamp)
+    pub date: u64,
+    /// Nonce used for encryption
+    pub nonce: [u8; 24],
+    /// Encrypted message data
+    pub box_data: Vec<u8>,
+    /// Message Authentication Code
+    pub mac: [u8; 32],
+    /// Public nickname of the sender, if set
+    pub nickname: Option<String>,
+}
+
+impl IncomingMessage {
+    /// Verify the MAC of this message.
+    ///
+    /// The MAC is a HMAC-SHA256 over the fields `from`, `to`, `messageId`,
+    /// `date`, `nonce` and `box`, keyed with the API secret. The comparison
+    /// is done in constant time.
+    ///
+    /// If the MAC does not match, [`ApiError::InvalidMac`] is returned. In
+    /// that case, the message must be discarded.
+    ///
+    /// [`ApiError::InvalidMac`]: errors/enum.ApiError.html#variant.InvalidMac
+    pub fn verify_mac(&self, secret: &str) -> Result<(), ApiError> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(self.from.as_bytes());
+        mac.update(self.to.as_bytes());
+        mac.update(self.message_id.to_string().as_bytes());
+        mac.update(self.date.to_string().as_bytes());
+        mac.update(HEXLOWER.encode(&self.nonce).as_bytes());
+        mac.update(HEXLOWER.encode(&self.box_data).as_bytes());
+        mac.verify_slice(&self.mac)
+            .map_err(|_| ApiError::InvalidMac)
+    }
+
+    /// Return the message date as `SystemTime`.
+    ///
+    /// If the date cannot be represented on this platform, `None` is
+    /// returned.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        UNIX_EPOCH.checked_add(Duration::from_secs(self.date))
+    }
+
+    /// Return whether the message date differs from the current time by at
+    /// most `max_age`.
+    ///
+    /// Rejecting messages that are not fresh protects against replayed
+    /// callbacks. The check works in both directions to account for clock
+    /// skew between the sender and your server. Messages with a date that
+    /// cannot be represented are never fresh.
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        self.is_fresh_at(max_age, SystemTime::now())
+    }
+
+    fn is_fresh_at(&self, max_age: Duration, now: SystemTime) -> bool {
+        let timestamp = match self.timestamp() {
+            Some(timestamp) => timestamp,
+            None => return false,
+        };
+        let difference = match now.duration_since(timestamp) {
+            Ok(age) => age,
+            Err(e) => e.duration(),
+        };
+        difference <= max_age
+    }
+}
+
+/// Return the value of a required form field.
+fn required<'a>(fields: &'a HashMap<String, String>, name: &str) -> Result<&'a str, ApiError> {
+    fields
+        .get(name)
+        .map(String::as_str)
+        .ok_or_else(|| ApiError::Other(format!("Missing field in incoming message: {}", name)))
+}
+
+/// Decode a hex encoded form field.
+fn decode_hex(name: &str, value: &str) -> Result<Vec<u8>, ApiError> {
+    HEXLOWER_PERMISSIVE
+        .decode(value.as_bytes())
+        .map_err(|e| ApiError::Other(format!("Could not decode {} hex string: {}", name, e)))
+}
+
+/// Decode a hex encoded form field with a fixed length.
+fn decode_hex_array<const N: usize>(name: &str, value: &str) -> Result<[u8; N], ApiError> {
+    let bytes = decode_hex(name, value)?;
+    if bytes.len() != N {
+        return Err(ApiError::Other(format!(
+            "Invalid {} length: Expected {} bytes, got {}",
+            name,
+            N,
+            bytes.len()
+        )));
+    }
+    let mut arr = [0; N];
+    arr.copy_from_slice(&bytes);
+    Ok(arr)
+}
+
+/// Parse the form encoded request body of an incoming message.
+///
+/// The Threema Gateway delivers incoming messages to your callback URL as a
+/// `POST` request with an `application/x-www-form-urlencoded` body. Pass
+/// that body to this function to parse it.
+///
+/// Note that this function does not verify the MAC of the message. Use
+/// [`IncomingMessage::verify_mac`](struct.IncomingMessage.html#method.verify_mac)
+/// to do that.
+pub fn parse_incoming(body: &[u8]) -> Result<IncomingMessage, ApiError> {
+    let fields: HashMap<String, String> = form_urlencoded::parse(body).into_owned().collect();
+
+    let date = required(&fields, "date")?;
+    Ok(IncomingMessage {
+        from: required(&fields, "from")?.to_string(),
+        to: required(&fields, "to")?.to_string(),
+        message_id: MessageId::from_str(required(&fields, "messageId")?)?,
+        date: date
+            .parse()
+            .map_err(|_| ApiError::Other(format!("Invalid date in incoming message: {}", date)))?,
+        nonce: decode_hex_array("nonce", required(&fields, "nonce")?)?,
+        box_data: decode_hex("box", required(&fields, "box")?)?,
+        mac: decode_hex_array("mac", required(&fields, "mac")?)?,
+        nickname: fields.get("nickname").cloned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = b"from=ECHOECHO&to=*TESTTST&messageId=0123456789abcdef&date=1594744526\
+        &nonce=000102030405060708090a0b0c0d0e0f1011121314151617&box=deadbeef\
+        &mac=00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff\
+        &nickname=Test+User";
+
+    #[test]
+    fn test_parse_incoming() {
+        let msg = parse_incoming(BODY).unwrap();
+        assert_eq!(msg.from, "ECHOECHO");
+        assert_eq!(msg.to, "*TESTTST");
+        assert_eq!(msg.message_id.to_string(), "0123456789abcdef");
+        assert_eq!(msg.date, 1594744526);
+        assert_eq!(msg.nonce[0], 0x00);
+        assert_eq!(msg.nonce[23], 0x17);
+        assert_eq!(msg.box_data, vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(msg.mac[0], 0x00);
+        assert_eq!(msg.mac[31], 0xff);
+        assert_eq!(msg.nickname, Some("Test User".into()));
+    }
+
+    #[test]
+    fn test_verify_mac() {
+        let body = String::from_utf8(BODY.to_vec()).unwrap();
+        let body = body.replace(
+            "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff",
+            "0d3737ab391320724c2aa25af3c951fe672657428b46e5cfffe8ab407917e610",
+        );
+        let msg = parse_incoming(body.as_bytes()).unwrap();
+        assert!(msg.verify_mac("secret").is_ok());
+        match msg.verify_mac("wrong-secret") {
+            Err(ApiError::InvalidMac) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_mac_tampered() {
+        let body = String::from_utf8(BODY.to_vec()).unwrap();
+        let body = body.replace(
+            "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff",
+            "0d3737ab391320724c2aa25af3c951fe672657428b46e5cfffe8ab407917e610",
+        );
+        let mut msg = parse_incoming(body.as_bytes()).unwrap();
+        msg.date += 1;
+        match msg.verify_mac("secret") {
+            Err(ApiError::InvalidMac) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_incoming_without_nickname() {
+        let body = String::from_utf8(BODY.to_vec()).unwrap();
+        let body = body.replace("&nickname=Test+User", "");
+        let msg = parse_incoming(body.as_bytes()).unwrap();
+        assert_eq!(msg.nickname, None);
+    }
+
+    #[test]
+    fn test_parse_incoming_missing_field() {
+        let body = String::from_utf8(BODY.to_vec()).unwrap();
+        let body = body.replace("&box=deadbeef", "");
+        match parse_incoming(body.as_bytes()) {
+            Err(ApiError::Other(msg)) => assert!(msg.contains("box")),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_incoming_bad_nonce_length() {
+        let body = String::from_utf8(BODY.to_vec()).unwrap();
+        let body = body.replace("1011121314151617", "10111213141516");
+        assert!(parse_incoming(body.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_incoming_bad_date() {
+        let body = String::from_utf8(BODY.to_vec()).unwrap();
+        let body = body.replace("1594744526", "yesterday");
+        assert!(parse_incoming(body.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_timestamp_and_freshness() {
+        let mut msg = parse_incoming(BODY).unwrap();
+        let timestamp = msg.timestamp().unwrap();
+        assert_eq!(timestamp, UNIX_EPOCH + Duration::from_secs(1594744526));
+
+        let max_age = Duration::from_secs(60);
+        assert!(msg.is_fresh_at(max_age, timestamp + Duration::from_secs(60)));
+        assert!(!msg.is_fresh_at(max_age, timestamp + Duration::from_secs(61)));
+        assert!(msg.is_fresh_at(max_age, timestamp - Duration::from_secs(60)));
+        assert!(!msg.is_fresh_at(max_age, timestamp - Duration::from_secs(61)));
+        assert!(!msg.is_fresh(max_age));
+
+        msg.date = u64::MAX;
+        assert_eq!(msg.timestamp(), None);
+        assert!(!msg.is_fresh(Duration::from_secs(u64::MAX)));
+    }
+}
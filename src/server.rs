@@ -0,0 +1,347 @@
+//! A minimal HTTP server that receives incoming messages.
+//!
+//! This module is only available with the `server` feature.
+
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::Stream;
+use hyper::body::HttpBody;
+use hyper::header::CONTENT_LENGTH;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::api::AsyncE2eApi;
+use crate::crypto::DecryptedMessage;
+use crate::errors::ApiError;
+use crate::receive::{parse_incoming, IncomingMessage};
+
+/// The number of received messages that are buffered before the server
+/// stops accepting new messages. If the buffer is full, incoming messages are
+/// rejected with status 503, so that the Threema Gateway retries the delivery
+/// later.
+const CHANNEL_CAPACITY: usize = 32;
+
+/// The maximum size of a request body in bytes. Incoming messages are much
+/// smaller, so larger requests are rejected before they are authenticated.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// A stream of incoming messages, received by the server started with
+/// [`AsyncE2eApi::listen`](struct.AsyncE2eApi.html#method.listen).
+///
+/// Every item consists of the incoming message (containing the sender and
+/// the message ID) and the decrypted message. The server is shut down when
+/// the stream is dropped.
+#[derive(Debug)]
+pub struct IncomingStream {
+    rx: mpsc::Receiver<(IncomingMessage, DecryptedMessage)>,
+    local_addr: SocketAddr,
+}
+
+impl IncomingStream {
+    /// Return the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Stream for IncomingStream {
+    type Item = (IncomingMessage, DecryptedMessage);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl AsyncE2eApi {
+    /// Start an HTTP server that receives incoming messages on the specified
+    /// address, and return a stream of the received messages.
+    ///
+    /// Every `POST` request is parsed with
+    /// [`parse_incoming`](fn.parse_incoming.html), its MAC is verified with
+    /// [`verify_mac`](#method.verify_mac) and it is decrypted with
+    /// [`decrypt`](#method.decrypt), using the public key of the sender
+    /// (see [`lookup_pubkey_cached`](#method.lookup_pubkey_cached)). Invalid
+    /// messages are rejected with an error status and not passed to the
+    /// stream. If the public key lookup fails, status 500 is returned, so
+    /// that the Threema Gateway retries the delivery later. The same applies
+    /// if too many received messages have not been taken from the stream yet
+    /// (status 503). Requests with a body larger than 64 KiB are rejected with
+    /// status 413.
+    ///
+    /// The server runs as a task on the current tokio runtime. It does not
+    /// handle TLS, so it should be placed behind a reverse proxy.
+    pub async fn listen(&self, addr: SocketAddr) -> Result<IncomingStream, ApiError> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let builder = Server::from_tcp(listener)
+            .map_err(|e| ApiError::Other(format!("Could not start server: {}", e)))?;
+
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let shutdown_tx = tx.clone();
+        let api = self.clone();
+        let make_service = make_service_fn(move |_| {
+            let api = api.clone();
+            let tx = tx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let api = api.clone();
+                    let tx = tx.clone();
+                    async move { Ok::<_, Infallible>(handle_request(&api, &tx, req).await) }
+                }))
+            }
+        });
+        let server = builder
+            .serve(make_service)
+            .with_graceful_shutdown(async move { shutdown_tx.closed().await });
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                warn!("Server for incoming messages failed: {}", e);
+            }
+        });
+
+        debug!("Listening for incoming messages on {}", local_addr);
+        Ok(IncomingStream { rx, local_addr })
+    }
+}
+
+/// Handle a request containing an incoming message.
+async fn handle_request(
+    api: &AsyncE2eApi,
+    tx: &mpsc::Sender<(IncomingMessage, DecryptedMessage)>,
+    req: Request<Body>,
+) -> Response<Body> {
+    let status = match receive(api, tx, req).await {
+        Ok(()) => StatusCode::OK,
+        Err(status) => status,
+    };
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// Parse, verify and decrypt an incoming message and pass it to the stream.
+async fn receive(
+    api: &AsyncE2eApi,
+    tx: &mpsc::Sender<(IncomingMessage, DecryptedMessage)>,
+    req: Request<Body>,
+) -> Result<(), StatusCode> {
+    if req.method() != Method::POST {
+        return Err(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let body = read_body(req).await?;
+    let incoming = parse_incoming(&body).map_err(|e| {
+        warn!("Could not parse incoming message: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    api.verify_mac(&incoming).map_err(|_| {
+        warn!("Discarding incoming message with invalid MAC");
+        StatusCode::UNAUTHORIZED
+    })?;
+    let sender_key = api
+        .lookup_pubkey_cached(&incoming.from)
+        .await
+        .map_err(|e| {
+            warn!("Could not look up public key of {}: {}", incoming.from, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let message = api
+        .decrypt(&incoming.box_data, &incoming.nonce, &sender_key)
+        .map_err(|e| {
+            warn!("Could not decrypt incoming message: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+    tx.try_send((incoming, message)).map_err(|e| {
+        if let TrySendError::Full(_) = e {
+            warn!("Rejecting incoming message, too many messages are not yet processed");
+        }
+        StatusCode::SERVICE_UNAVAILABLE
+    })
+}
+
+/// Read the body of a request, rejecting bodies larger than
+/// [`MAX_BODY_SIZE`] with status 413.
+async fn read_body(req: Request<Body>) -> Result<Vec<u8>, StatusCode> {
+    let content_length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len > MAX_BODY_SIZE as u64) {
+        warn!("Rejecting incoming request with a too large body");
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    // The Content-Length header may be missing, so the size must be checked
+    // while reading as well
+    let mut body = req.into_body();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if data.len() + chunk.len() > MAX_BODY_SIZE {
+            warn!("Rejecting incoming request with a too large body");
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use data_encoding::HEXLOWER;
+    use futures_util::StreamExt;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    use super::*;
+    use crate::crypto::encrypt;
+    use crate::mock::{MockServer, MOCK_ID, MOCK_SECRET};
+    use crate::types::MessageType;
+
+    fn incoming_body(from: &str, nonce: &[u8; 24], ciphertext: &[u8], secret: &str) -> String {
+        let nonce = HEXLOWER.encode(nonce);
+        let ciphertext = HEXLOWER.encode(ciphertext);
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        for field in &[
+            from,
+            MOCK_ID,
+            "0123456789abcdef",
+            "1594744526",
+            &nonce,
+            &ciphertext,
+        ] {
+            mac.update(field.as_bytes());
+        }
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair("from", from)
+            .append_pair("to", MOCK_ID)
+            .append_pair("messageId", "0123456789abcdef")
+            .append_pair("date", "1594744526")
+            .append_pair("nonce", &nonce)
+            .append_pair("box", &ciphertext)
+            .append_pair("mac", &HEXLOWER.encode(&mac.finalize().into_bytes()))
+            .finish()
+    }
+
+    #[tokio::test]
+    async fn test_listen() {
+        let server = MockServer::start().unwrap();
+        let (own_public_key, own_private_key) = crate::generate_keypair();
+        let api = server
+            .api_builder()
+            .with_private_key(own_private_key)
+            .into_e2e_async()
+            .unwrap();
+        let (sender_public_key, sender_private_key) = crate::generate_keypair();
+        server.add_pubkey("ECHOECHO", &sender_public_key.into());
+        let encrypted = encrypt(
+            b"Hello bot",
+            MessageType::Text,
+            &own_public_key,
+            &sender_private_key,
+        );
+
+        let mut stream = api.listen("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let url = format!("http://{}/", stream.local_addr());
+        let client = reqwest::Client::new();
+        let post = |body: String| {
+            client
+                .post(&url)
+                .header("content-type", "application/x-www-form-urlencoded")
+                .body(body)
+                .send()
+        };
+
+        let body = incoming_body("ECHOECHO", &encrypted.nonce, &encrypted.ciphertext, "wrong");
+        assert_eq!(post(body).await.unwrap().status(), 401);
+        assert_eq!(post("invalid".into()).await.unwrap().status(), 400);
+
+        let body = incoming_body(
+            "ECHOECHO",
+            &encrypted.nonce,
+            &encrypted.ciphertext,
+            MOCK_SECRET,
+        );
+        assert_eq!(post(body).await.unwrap().status(), 200);
+        let (incoming, message) = stream.next().await.unwrap();
+        assert_eq!(incoming.from, "ECHOECHO");
+        assert_eq!(message, DecryptedMessage::Text("Hello bot".into()));
+    }
+
+    #[tokio::test]
+    async fn test_listen_channel_full() {
+        let server = MockServer::start().unwrap();
+        let (own_public_key, own_private_key) = crate::generate_keypair();
+        let api = server
+            .api_builder()
+            .with_private_key(own_private_key)
+            .into_e2e_async()
+            .unwrap();
+        let (sender_public_key, sender_private_key) = crate::generate_keypair();
+        server.add_pubkey("ECHOECHO", &sender_public_key.into());
+        let encrypted = encrypt(
+            b"Hello bot",
+            MessageType::Text,
+            &own_public_key,
+            &sender_private_key,
+        );
+
+        let mut stream = api.listen("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let url = format!("http://{}/", stream.local_addr());
+        let client = reqwest::Client::new();
+        let body = incoming_body(
+            "ECHOECHO",
+            &encrypted.nonce,
+            &encrypted.ciphertext,
+            MOCK_SECRET,
+        );
+        let post = || client.post(&url).body(body.clone()).send();
+
+        // Fill the buffer without taking messages from the stream
+        for _ in 0..CHANNEL_CAPACITY {
+            assert_eq!(post().await.unwrap().status(), 200);
+        }
+        assert_eq!(post().await.unwrap().status(), 503);
+
+        // After taking a message, there is room again
+        stream.next().await.unwrap();
+        assert_eq!(post().await.unwrap().status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_listen_body_too_large() {
+        let server = MockServer::start().unwrap();
+        let api = server.e2e_api_async();
+        let stream = api.listen("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let url = format!("http://{}/", stream.local_addr());
+        let client = reqwest::Client::new();
+
+        // With Content-Length header
+        let res = client
+            .post(&url)
+            .body(vec![b'a'; MAX_BODY_SIZE + 1])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 413);
+
+        // Chunked, without Content-Length header
+        let chunks = (0..4).map(|_| Ok::<_, io::Error>(vec![b'a'; MAX_BODY_SIZE / 2]));
+        let res = client
+            .post(&url)
+            .body(reqwest::Body::wrap_stream(futures_util::stream::iter(
+                chunks,
+            )))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 413);
+    }
+}
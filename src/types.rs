@@ -2,11 +2,19 @@ use std::default::Default;
 use std::fmt;
 use std::str::FromStr;
 use std::string::ToString;
+use std::time::SystemTime;
 
-use data_encoding::{HEXLOWER, HEXLOWER_PERMISSIVE};
+use data_encoding::{BASE64, HEXLOWER, HEXLOWER_PERMISSIVE};
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer};
 use serde::{Serialize, Serializer};
 
-use crate::errors::{ApiError, FileMessageBuilderError};
+use crate::backend::randombytes_into;
+use crate::crypto::RecipientKey;
+use crate::errors::{
+    ApiError, BallotMessageBuilderError, CryptoError, FileMessageBuilderError,
+    ImageMessageBuilderError,
+};
 use crate::{Key, Mime};
 
 /// A message type.
@@ -14,28 +22,116 @@ use crate::{Key, Mime};
 pub enum MessageType {
     Text,
     Image,
+    Location,
     Video,
+    Audio,
     File,
+    BallotCreate,
+    GroupText,
+    GroupImage,
+    GroupSetup,
+    GroupName,
+    GroupSetPhoto,
+    GroupDeletePhoto,
     DeliveryReceipt,
+    ForwardSecurityEnvelope,
 }
 
-impl Into<u8> for MessageType {
-    fn into(self) -> u8 {
+impl MessageType {
+    /// Parse the message type from the type byte, which is the first byte of
+    /// a decrypted message. Unknown type bytes return `None`.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x01 => Some(MessageType::Text),
+            0x02 => Some(MessageType::Image),
+            0x10 => Some(MessageType::Location),
+            0x13 => Some(MessageType::Video),
+            0x14 => Some(MessageType::Audio),
+            0x15 => Some(MessageType::BallotCreate),
+            0x17 => Some(MessageType::File),
+            0x41 => Some(MessageType::GroupText),
+            0x43 => Some(MessageType::GroupImage),
+            0x4a => Some(MessageType::GroupSetup),
+            0x4b => Some(MessageType::GroupName),
+            0x50 => Some(MessageType::GroupSetPhoto),
+            0x54 => Some(MessageType::GroupDeletePhoto),
+            0x80 => Some(MessageType::DeliveryReceipt),
+            0xa0 => Some(MessageType::ForwardSecurityEnvelope),
+            _ => None,
+        }
+    }
+
+    /// Return the type byte of this message type.
+    pub fn to_byte(&self) -> u8 {
+        (*self).into()
+    }
+
+    /// Return the capability a recipient needs to receive messages of this
+    /// type, e.g. `"file"` for file messages. Text and location messages as
+    /// well as delivery receipts need no capability and return `None`.
+    ///
+    /// The name can be checked with
+    /// [`Capabilities::can`](struct.Capabilities.html#method.can):
+    ///
+    /// ```
+    /// use threema_gateway::{Capabilities, MessageType};
+    ///
+    /// let capabilities: Capabilities = "text,image".parse().unwrap();
+    /// let can_receive = |msgtype: MessageType| {
+    ///     msgtype
+    ///         .required_capability()
+    ///         .map_or(true, |capability| capabilities.can(capability))
+    /// };
+    /// assert!(can_receive(MessageType::Image));
+    /// assert!(!can_receive(MessageType::File));
+    /// ```
+    pub fn required_capability(&self) -> Option<&'static str> {
         match self {
+            MessageType::Text | MessageType::Location | MessageType::DeliveryReceipt => None,
+            MessageType::Image => Some("image"),
+            MessageType::Video => Some("video"),
+            MessageType::Audio => Some("audio"),
+            MessageType::File => Some("file"),
+            MessageType::BallotCreate => Some("ballot"),
+            MessageType::GroupText
+            | MessageType::GroupImage
+            | MessageType::GroupSetup
+            | MessageType::GroupName
+            | MessageType::GroupSetPhoto
+            | MessageType::GroupDeletePhoto => Some("group"),
+            MessageType::ForwardSecurityEnvelope => Some("pfs"),
+        }
+    }
+}
+
+impl From<MessageType> for u8 {
+    fn from(val: MessageType) -> Self {
+        match val {
             MessageType::Text => 0x01,
             MessageType::Image => 0x02,
+            MessageType::Location => 0x10,
             MessageType::Video => 0x13,
+            MessageType::Audio => 0x14,
+            MessageType::BallotCreate => 0x15,
             MessageType::File => 0x17,
+            MessageType::GroupText => 0x41,
+            MessageType::GroupImage => 0x43,
+            MessageType::GroupSetup => 0x4a,
+            MessageType::GroupName => 0x4b,
+            MessageType::GroupSetPhoto => 0x50,
+            MessageType::GroupDeletePhoto => 0x54,
             MessageType::DeliveryReceipt => 0x80,
+            MessageType::ForwardSecurityEnvelope => 0xa0,
         }
     }
 }
 
 /// The rendering type influences how a file message is displayed on the device
 /// of the recipient.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
 pub enum RenderingType {
     /// Display as default file message
+    #[default]
     File,
     /// Display as media file message (e.g. image or audio message)
     Media,
@@ -43,35 +139,46 @@ pub enum RenderingType {
     Sticker,
 }
 
-impl Into<u8> for RenderingType {
-    fn into(self) -> u8 {
-        match self {
-            Self::File => 0,
-            Self::Media => 1,
-            Self::Sticker => 2,
+impl From<RenderingType> for u8 {
+    fn from(val: RenderingType) -> Self {
+        match val {
+            RenderingType::File => 0,
+            RenderingType::Media => 1,
+            RenderingType::Sticker => 2,
         }
     }
 }
 
 impl Serialize for RenderingType {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_u8(self.clone().into())
+        serializer.serialize_u8((*self).into())
     }
 }
 
-impl Default for RenderingType {
-    fn default() -> Self {
-        RenderingType::File
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for RenderingType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match u8::deserialize(deserializer)? {
+            0 => Ok(RenderingType::File),
+            1 => Ok(RenderingType::Media),
+            2 => Ok(RenderingType::Sticker),
+            other => Err(de::Error::custom(format!(
+                "Invalid rendering type: {}",
+                other
+            ))),
+        }
     }
 }
 
 /// A file message.
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct FileMessage {
     #[serde(rename = "b")]
     file_blob_id: BlobId,
     #[serde(rename = "m")]
     #[serde(serialize_with = "serialize_to_string")]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_from_str"))]
     file_media_type: Mime,
 
     #[serde(rename = "t")]
@@ -80,10 +187,15 @@ pub struct FileMessage {
     #[serde(rename = "p")]
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(serialize_with = "serialize_opt_to_string")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, deserialize_with = "deserialize_opt_from_str")
+    )]
     thumbnail_media_type: Option<Mime>,
 
     #[serde(rename = "k")]
     #[serde(serialize_with = "key_to_hex")]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "key_from_hex"))]
     blob_encryption_key: Key,
 
     #[serde(rename = "n")]
@@ -94,6 +206,9 @@ pub struct FileMessage {
     #[serde(rename = "d")]
     #[serde(skip_serializing_if = "Option::is_none")]
     description: Option<String>,
+    #[serde(rename = "c")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
 
     #[serde(rename = "j")]
     rendering_type: RenderingType,
@@ -109,6 +224,7 @@ pub struct FileMessage {
 ///
 /// This data is intended to enhance the layout logic.
 #[derive(Debug, Serialize, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 struct FileMetadata {
     #[serde(rename = "a")]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -161,6 +277,7 @@ pub struct FileMessageBuilder {
     file_name: Option<String>,
     file_size_bytes: u32,
     description: Option<String>,
+    correlation_id: Option<String>,
     rendering_type: RenderingType,
     reserved: u8,
     metadata: Option<FileMetadata>,
@@ -196,6 +313,7 @@ impl FileMessageBuilder {
             file_name: None,
             file_size_bytes,
             description: None,
+            correlation_id: None,
             rendering_type: RenderingType::File,
             reserved: 0,
             metadata: None,
@@ -267,6 +385,27 @@ impl FileMessageBuilder {
         self
     }
 
+    /// Set the file caption.
+    ///
+    /// This is an alias for [`description`](#method.description).
+    pub fn caption(self, caption: impl Into<String>) -> Self {
+        self.description_opt(Some(caption))
+    }
+
+    /// Set the correlation ID.
+    ///
+    /// Clients use the correlation ID to group related media files (e.g. all
+    /// images that were sent together).
+    pub fn correlation_id(self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id_opt(Some(correlation_id))
+    }
+
+    /// Set the correlation ID from an Option.
+    pub fn correlation_id_opt(mut self, correlation_id: Option<impl Into<String>>) -> Self {
+        self.correlation_id = correlation_id.map(Into::into);
+        self
+    }
+
     /// Set the rendering type.
     ///
     /// See [`RenderingType`](enum.RenderingType.html) docs for more information.
@@ -346,6 +485,7 @@ impl FileMessageBuilder {
             file_name: self.file_name,
             file_size_bytes: self.file_size_bytes,
             description: self.description,
+            correlation_id: self.correlation_id,
             rendering_type: self.rendering_type,
             reserved: self.reserved,
             metadata: self.metadata,
@@ -353,8 +493,308 @@ impl FileMessageBuilder {
     }
 }
 
-/// A 16-byte blob ID.
+/// Whether one or multiple choices may be selected in a ballot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BallotAssessment {
+    /// Only one choice may be selected
+    #[default]
+    SingleChoice,
+    /// Multiple choices may be selected
+    MultipleChoice,
+}
+
+impl From<BallotAssessment> for u8 {
+    fn from(val: BallotAssessment) -> Self {
+        match val {
+            BallotAssessment::SingleChoice => 0,
+            BallotAssessment::MultipleChoice => 1,
+        }
+    }
+}
+
+impl Serialize for BallotAssessment {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8((*self).into())
+    }
+}
+
+/// When the results of a ballot are visible to the participants.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BallotType {
+    /// Results are only shown once the ballot is closed
+    #[default]
+    ResultOnClose,
+    /// Intermediate results are shown while the ballot is open
+    Intermediate,
+}
+
+impl From<BallotType> for u8 {
+    fn from(val: BallotType) -> Self {
+        match val {
+            BallotType::ResultOnClose => 0,
+            BallotType::Intermediate => 1,
+        }
+    }
+}
+
+impl Serialize for BallotType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8((*self).into())
+    }
+}
+
+/// A ballot (poll) creation message.
+#[derive(Debug, Serialize)]
+pub struct BallotMessage {
+    #[serde(skip)]
+    ballot_id: [u8; 8],
+
+    #[serde(rename = "d")]
+    description: String,
+    #[serde(rename = "s")]
+    state: u8,
+    #[serde(rename = "a")]
+    assessment: BallotAssessment,
+    #[serde(rename = "t")]
+    ballot_type: BallotType,
+    #[serde(rename = "o")]
+    choice_type: u8,
+    #[serde(rename = "c")]
+    choices: Vec<BallotChoice>,
+    #[serde(rename = "p")]
+    participants: Vec<String>,
+}
+
+/// A single choice of a ballot.
+#[derive(Debug, Serialize)]
+struct BallotChoice {
+    #[serde(rename = "i")]
+    id: u32,
+    #[serde(rename = "n")]
+    name: String,
+    #[serde(rename = "o")]
+    order: u32,
+    #[serde(rename = "r")]
+    result: Vec<u8>,
+}
+
+impl BallotMessage {
+    /// Shortcut for [`BallotMessageBuilder::new`](struct.BallotMessageBuilder.html#method.new).
+    pub fn builder(description: impl Into<String>) -> BallotMessageBuilder {
+        BallotMessageBuilder::new(description)
+    }
+
+    /// Return the 8 byte ID of this ballot.
+    ///
+    /// The ballot ID is needed to associate votes with this ballot.
+    pub fn ballot_id(&self) -> &[u8; 8] {
+        &self.ballot_id
+    }
+}
+
+/// Builder for [`BallotMessage`](struct.BallotMessage.html).
+pub struct BallotMessageBuilder {
+    ballot_id: Option<[u8; 8]>,
+    description: String,
+    assessment: BallotAssessment,
+    ballot_type: BallotType,
+    choices: Vec<String>,
+}
+
+impl BallotMessageBuilder {
+    /// Create a new [`BallotMessage`] builder with the specified title.
+    ///
+    /// [`BallotMessage`]: struct.BallotMessage.html
+    pub fn new(description: impl Into<String>) -> Self {
+        BallotMessageBuilder {
+            ballot_id: None,
+            description: description.into(),
+            assessment: BallotAssessment::default(),
+            ballot_type: BallotType::default(),
+            choices: Vec::new(),
+        }
+    }
+
+    /// Set the ballot ID.
+    ///
+    /// If no ballot ID is set, a random ID is generated.
+    pub fn ballot_id(mut self, ballot_id: [u8; 8]) -> Self {
+        self.ballot_id = Some(ballot_id);
+        self
+    }
+
+    /// Add a choice. Choices are displayed in the order they were added.
+    pub fn choice(mut self, name: impl Into<String>) -> Self {
+        self.choices.push(name.into());
+        self
+    }
+
+    /// Add multiple choices.
+    pub fn choices<I, T>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.choices.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set whether one or multiple choices may be selected.
+    ///
+    /// See [`BallotAssessment`](enum.BallotAssessment.html) docs for more information.
+    pub fn assessment(mut self, assessment: BallotAssessment) -> Self {
+        self.assessment = assessment;
+        self
+    }
+
+    /// Set when the results are visible to the participants.
+    ///
+    /// See [`BallotType`](enum.BallotType.html) docs for more information.
+    pub fn ballot_type(mut self, ballot_type: BallotType) -> Self {
+        self.ballot_type = ballot_type;
+        self
+    }
+
+    /// Create a [`BallotMessage`] from this builder.
+    ///
+    /// A ballot needs a non-empty title and at least two choices.
+    ///
+    /// [`BallotMessage`]: struct.BallotMessage.html
+    pub fn build(self) -> Result<BallotMessage, BallotMessageBuilderError> {
+        if self.description.trim().is_empty() {
+            return Err(BallotMessageBuilderError::Invalid(
+                "Ballot title may not be empty",
+            ));
+        }
+        if self.choices.len() < 2 {
+            return Err(BallotMessageBuilderError::Invalid(
+                "Ballot must contain at least two choices",
+            ));
+        }
+
+        let ballot_id = self.ballot_id.unwrap_or_else(|| {
+            let mut id = [0; 8];
+            randombytes_into(&mut id);
+            id
+        });
+        let choices = self
+            .choices
+            .into_iter()
+            .zip(0..)
+            .map(|(name, i)| BallotChoice {
+                id: i,
+                name,
+                order: i,
+                result: Vec::new(),
+            })
+            .collect();
+
+        Ok(BallotMessage {
+            ballot_id,
+            description: self.description,
+            state: 0,
+            assessment: self.assessment,
+            ballot_type: self.ballot_type,
+            choice_type: 0,
+            choices,
+            participants: Vec::new(),
+        })
+    }
+}
+
+/// An image message.
+///
+/// The image data (JPEG format) must be encrypted with
+/// [`E2eApi::encrypt_raw`](struct.E2eApi.html#method.encrypt_raw) and uploaded
+/// to the blob server before the message can be sent.
 #[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ImageMessage {
+    blob_id: BlobId,
+    img_size_bytes: u32,
+    image_data_nonce: [u8; 24],
+}
+
+impl ImageMessage {
+    /// Shortcut for [`ImageMessageBuilder::new`](struct.ImageMessageBuilder.html#method.new).
+    pub fn builder() -> ImageMessageBuilder {
+        ImageMessageBuilder::new()
+    }
+
+    /// Return the blob ID of the encrypted image data.
+    pub fn blob_id(&self) -> &BlobId {
+        &self.blob_id
+    }
+
+    /// Return the size of the image in bytes.
+    pub fn img_size_bytes(&self) -> u32 {
+        self.img_size_bytes
+    }
+
+    /// Return the nonce that was used to encrypt the image data.
+    pub fn image_data_nonce(&self) -> &[u8; 24] {
+        &self.image_data_nonce
+    }
+}
+
+/// Builder for [`ImageMessage`](struct.ImageMessage.html).
+#[derive(Debug, Default)]
+pub struct ImageMessageBuilder {
+    blob_id: Option<BlobId>,
+    img_size_bytes: Option<u32>,
+    image_data_nonce: Option<[u8; 24]>,
+}
+
+impl ImageMessageBuilder {
+    /// Create a new, empty [`ImageMessage`] builder.
+    ///
+    /// [`ImageMessage`]: struct.ImageMessage.html
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the blob ID of the encrypted image data.
+    pub fn blob_id(mut self, blob_id: BlobId) -> Self {
+        self.blob_id = Some(blob_id);
+        self
+    }
+
+    /// Set the size of the image in bytes.
+    ///
+    /// Note that the size is only used for download size displaying purposes
+    /// and has no security implications.
+    pub fn size(mut self, img_size_bytes: u32) -> Self {
+        self.img_size_bytes = Some(img_size_bytes);
+        self
+    }
+
+    /// Set the nonce that was used to encrypt the image data.
+    pub fn nonce(mut self, image_data_nonce: [u8; 24]) -> Self {
+        self.image_data_nonce = Some(image_data_nonce);
+        self
+    }
+
+    /// Create an [`ImageMessage`] from this builder.
+    ///
+    /// The blob ID, the size and the nonce are required.
+    ///
+    /// [`ImageMessage`]: struct.ImageMessage.html
+    pub fn build(self) -> Result<ImageMessage, ImageMessageBuilderError> {
+        Ok(ImageMessage {
+            blob_id: self
+                .blob_id
+                .ok_or(ImageMessageBuilderError::MissingField("blob_id"))?,
+            img_size_bytes: self
+                .img_size_bytes
+                .ok_or(ImageMessageBuilderError::MissingField("size"))?,
+            image_data_nonce: self
+                .image_data_nonce
+                .ok_or(ImageMessageBuilderError::MissingField("nonce"))?,
+        })
+    }
+}
+
+/// A 16-byte blob ID.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct BlobId(pub [u8; 16]);
 
 impl BlobId {
@@ -362,6 +802,34 @@ impl BlobId {
     pub fn new(id: [u8; 16]) -> Self {
         BlobId(id)
     }
+
+    /// Create a new BlobId from a base64 encoded String.
+    pub fn from_base64(id: &str) -> Result<Self, ApiError> {
+        let bytes = BASE64
+            .decode(id.as_bytes())
+            .map_err(|_| ApiError::BadBlobId)?;
+        if bytes.len() != 16 {
+            return Err(ApiError::BadBlobId);
+        }
+        let mut arr = [0; 16];
+        arr.copy_from_slice(&bytes);
+        Ok(BlobId(arr))
+    }
+
+    /// Return a reference to the blob ID bytes.
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    /// Return the blob ID as a 32 character lowercase hexadecimal String.
+    pub fn to_hex(&self) -> String {
+        HEXLOWER.encode(&self.0)
+    }
+
+    /// Return the blob ID as a base64 encoded String.
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(&self.0)
+    }
 }
 
 impl FromStr for BlobId {
@@ -383,13 +851,439 @@ impl FromStr for BlobId {
 
 impl fmt::Display for BlobId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", HEXLOWER.encode(&self.0))
+        write!(f, "{}", self.to_hex())
     }
 }
 
 impl Serialize for BlobId {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_str(&HEXLOWER.encode(&self.0))
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for BlobId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_from_str(deserializer)
+    }
+}
+
+/// The result of a blob upload.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BlobUploadResult {
+    /// The ID of the uploaded blob
+    pub id: BlobId,
+    /// The point in time when the blob expires, if the server specified it
+    /// with an `Expires` header
+    pub expires_at: Option<SystemTime>,
+}
+
+/// An 8-byte message ID.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct MessageId(pub [u8; 8]);
+
+impl MessageId {
+    /// Create a new MessageId.
+    pub fn new(id: [u8; 8]) -> Self {
+        MessageId(id)
+    }
+
+    /// Return the message ID as a 16 character lowercase hexadecimal String.
+    pub fn as_hex(&self) -> String {
+        HEXLOWER.encode(&self.0)
+    }
+}
+
+impl FromStr for MessageId {
+    type Err = ApiError;
+
+    /// Create a new MessageId from a 16 character hexadecimal String.
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        let bytes = HEXLOWER_PERMISSIVE
+            .decode(id.as_bytes())
+            .map_err(|_| ApiError::Other(format!("Invalid message ID: {}", id)))?;
+        if bytes.len() != 8 {
+            return Err(ApiError::Other(format!("Invalid message ID: {}", id)));
+        }
+        let mut arr = [0; 8];
+        arr[..].clone_from_slice(&bytes[..]);
+        Ok(MessageId(arr))
+    }
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_hex())
+    }
+}
+
+/// The status reported by a delivery receipt.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReceiptStatus {
+    /// The message was received
+    Received,
+    /// The message was read
+    Read,
+    /// The message was explicitly acknowledged by the user
+    UserAcknowledged,
+    /// The message was explicitly declined by the user
+    UserDeclined,
+}
+
+impl From<ReceiptStatus> for u8 {
+    fn from(val: ReceiptStatus) -> Self {
+        match val {
+            ReceiptStatus::Received => 0x01,
+            ReceiptStatus::Read => 0x02,
+            ReceiptStatus::UserAcknowledged => 0x03,
+            ReceiptStatus::UserDeclined => 0x04,
+        }
+    }
+}
+
+/// A delivery receipt, confirming the reception of one or more messages.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DeliveryReceipt {
+    /// The delivery status
+    pub status: ReceiptStatus,
+    /// The IDs of the messages this receipt refers to
+    pub message_ids: Vec<MessageId>,
+}
+
+impl DeliveryReceipt {
+    /// Create a new delivery receipt.
+    pub fn new(status: ReceiptStatus, message_ids: Vec<MessageId>) -> Self {
+        DeliveryReceipt {
+            status,
+            message_ids,
+        }
+    }
+
+    /// Encode the delivery receipt payload: The status byte, followed by the
+    /// 8 byte message IDs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + self.message_ids.len() * 8);
+        bytes.push(self.status.into());
+        for message_id in &self.message_ids {
+            bytes.extend_from_slice(&message_id.0);
+        }
+        bytes
+    }
+
+    /// Decode a delivery receipt payload.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        let (status, ids) = bytes
+            .split_first()
+            .ok_or_else(|| CryptoError::BadMessage("Delivery receipt is empty".into()))?;
+        let status = match *status {
+            0x01 => ReceiptStatus::Received,
+            0x02 => ReceiptStatus::Read,
+            0x03 => ReceiptStatus::UserAcknowledged,
+            0x04 => ReceiptStatus::UserDeclined,
+            other => {
+                return Err(CryptoError::BadMessage(format!(
+                    "Invalid delivery receipt status: {:#04x}",
+                    other
+                )))
+            }
+        };
+        if ids.is_empty() || ids.len() % 8 != 0 {
+            return Err(CryptoError::BadMessage(format!(
+                "Invalid delivery receipt message ID list length: {}",
+                ids.len()
+            )));
+        }
+        let message_ids = ids
+            .chunks_exact(8)
+            .map(|chunk| {
+                let mut id = [0; 8];
+                id.copy_from_slice(chunk);
+                MessageId(id)
+            })
+            .collect();
+        Ok(DeliveryReceipt {
+            status,
+            message_ids,
+        })
+    }
+}
+
+/// The ID of a forward security session.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FsSessionId(pub [u8; 16]);
+
+impl FsSessionId {
+    /// Generate a new random session ID.
+    pub fn random() -> Self {
+        let mut id = [0; 16];
+        randombytes_into(&mut id);
+        FsSessionId(id)
+    }
+}
+
+/// The reason for rejecting a forward security message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FsRejectCause {
+    /// The message could not be decrypted, e.g. because the session state
+    /// is out of sync
+    StateMismatch,
+    /// The session is not known
+    UnknownSession,
+    /// Forward security is disabled on the receiving side
+    DisabledByLocal,
+}
+
+impl From<FsRejectCause> for u8 {
+    fn from(val: FsRejectCause) -> Self {
+        match val {
+            FsRejectCause::StateMismatch => 0,
+            FsRejectCause::UnknownSession => 1,
+            FsRejectCause::DisabledByLocal => 2,
+        }
+    }
+}
+
+/// The reason for terminating a forward security session.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FsTerminateCause {
+    /// The session is not known
+    UnknownSession,
+    /// The session is reset, e.g. after restoring a backup
+    Reset,
+    /// Forward security was disabled by the sender
+    DisabledByLocal,
+    /// Forward security was disabled by the receiver
+    DisabledByRemote,
+}
+
+impl From<FsTerminateCause> for u8 {
+    fn from(val: FsTerminateCause) -> Self {
+        match val {
+            FsTerminateCause::UnknownSession => 0,
+            FsTerminateCause::Reset => 1,
+            FsTerminateCause::DisabledByLocal => 2,
+            FsTerminateCause::DisabledByRemote => 3,
+        }
+    }
+}
+
+/// A forward security (FS) control message.
+///
+/// These messages are used to set up and tear down forward secret sessions
+/// between two Threema IDs. Only the construction of the control messages is
+/// supported. In particular, the following is not supported:
+///
+/// - Session management: Generating and storing the ephemeral keys, deriving
+///   the session keys and keeping track of the session state is up to the
+///   caller.
+/// - Version negotiation: No supported version range is sent, which peers
+///   interpret as protocol version 1.0.
+/// - Encapsulated messages: Messages sent within a session (using the
+///   session keys and ratchets) cannot be created or decrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsControlMessage {
+    /// Initiate a new session with the specified ephemeral public key.
+    Init {
+        session_id: FsSessionId,
+        ephemeral_public_key: [u8; 32],
+    },
+    /// Accept a session initiated by the recipient, with the specified
+    /// ephemeral public key.
+    Accept {
+        session_id: FsSessionId,
+        ephemeral_public_key: [u8; 32],
+    },
+    /// Reject a message received within a session.
+    Reject {
+        session_id: FsSessionId,
+        rejected_message_id: MessageId,
+        cause: FsRejectCause,
+    },
+    /// Terminate a session.
+    Terminate {
+        session_id: FsSessionId,
+        cause: FsTerminateCause,
+    },
+}
+
+impl FsControlMessage {
+    /// Return the ID of the session this message refers to.
+    pub fn session_id(&self) -> &FsSessionId {
+        match self {
+            FsControlMessage::Init { session_id, .. }
+            | FsControlMessage::Accept { session_id, .. }
+            | FsControlMessage::Reject { session_id, .. }
+            | FsControlMessage::Terminate { session_id, .. } => session_id,
+        }
+    }
+
+    /// Encode the message as the protobuf `Envelope` used as payload of the
+    /// forward security envelope message type.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+        let field = match self {
+            FsControlMessage::Init {
+                ephemeral_public_key,
+                ..
+            } => {
+                write_proto_bytes(&mut content, 1, ephemeral_public_key);
+                2
+            }
+            FsControlMessage::Accept {
+                ephemeral_public_key,
+                ..
+            } => {
+                write_proto_bytes(&mut content, 1, ephemeral_public_key);
+                3
+            }
+            FsControlMessage::Reject {
+                rejected_message_id,
+                cause,
+                ..
+            } => {
+                // fixed64, wire type 1
+                content.push(1 << 3 | 1);
+                content.extend_from_slice(&rejected_message_id.0);
+                write_proto_varint_field(&mut content, 2, u8::from(*cause).into());
+                4
+            }
+            FsControlMessage::Terminate { cause, .. } => {
+                write_proto_varint_field(&mut content, 1, u8::from(*cause).into());
+                5
+            }
+        };
+        let mut bytes = Vec::with_capacity(content.len() + 22);
+        write_proto_bytes(&mut bytes, 1, &self.session_id().0);
+        write_proto_bytes(&mut bytes, field, &content);
+        bytes
+    }
+}
+
+/// Write a protobuf varint.
+fn write_proto_varint(buf: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        buf.push((val as u8) | 0x80);
+        val >>= 7;
+    }
+    buf.push(val as u8);
+}
+
+/// Write a protobuf varint field (wire type 0).
+fn write_proto_varint_field(buf: &mut Vec<u8>, field: u8, val: u64) {
+    buf.push(field << 3);
+    write_proto_varint(buf, val);
+}
+
+/// Write a protobuf length-delimited field (wire type 2).
+fn write_proto_bytes(buf: &mut Vec<u8>, field: u8, data: &[u8]) {
+    buf.push(field << 3 | 2);
+    write_proto_varint(buf, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+/// The number of credits charged for every sent message.
+pub const COST_PER_MESSAGE: u64 = 1;
+
+/// The number of credits charged for every uploaded blob.
+pub const COST_PER_BLOB: u64 = 1;
+
+/// Return the number of credits required to send the specified number of
+/// messages and to upload the specified number of blobs.
+///
+/// No request is sent, so this can be used to compare the cost of a
+/// broadcast against the remaining credits beforehand.
+pub fn cost_of(messages: usize, blobs: usize) -> Credits {
+    Credits(messages as u64 * COST_PER_MESSAGE + blobs as u64 * COST_PER_BLOB)
+}
+
+/// The number of remaining gateway credits.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct Credits(pub u64);
+
+impl Credits {
+    /// Return whether the number of credits is below the specified
+    /// threshold.
+    pub fn is_low(&self, threshold: u64) -> bool {
+        self.0 < threshold
+    }
+}
+
+impl FromStr for Credits {
+    type Err = ApiError;
+
+    /// Parse the number of credits from a decimal String.
+    fn from_str(val: &str) -> Result<Self, Self::Err> {
+        val.trim()
+            .parse::<u64>()
+            .map(Credits)
+            .map_err(|_| ApiError::ParseError(format!("Could not parse credits: \"{}\"", val)))
+    }
+}
+
+impl fmt::Display for Credits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A named list of recipients, maintained locally.
+///
+/// Unlike a Threema group, a distribution list is not known to the
+/// recipients: Every member receives a regular one-to-one message. Send to
+/// all members with
+/// [`E2eApi::send_text_to_list`](struct.E2eApi.html#method.send_text_to_list).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistributionList {
+    name: String,
+    members: Vec<(String, RecipientKey)>,
+}
+
+impl DistributionList {
+    /// Create a new, empty distribution list.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        DistributionList {
+            name: name.into(),
+            members: Vec::new(),
+        }
+    }
+
+    /// Return the name of the list.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Add a member with its Threema ID and public key.
+    ///
+    /// If the ID is already a member, its public key is replaced.
+    pub fn add_member<I: Into<String>>(&mut self, id: I, public_key: RecipientKey) {
+        let id = id.into();
+        match self.members.iter_mut().find(|(member, _)| *member == id) {
+            Some(member) => member.1 = public_key,
+            None => self.members.push((id, public_key)),
+        }
+    }
+
+    /// Remove the member with the specified Threema ID. Return whether it
+    /// was a member.
+    pub fn remove_member(&mut self, id: &str) -> bool {
+        let len = self.members.len();
+        self.members.retain(|(member, _)| member != id);
+        self.members.len() != len
+    }
+
+    /// Return the members in the order they were added.
+    pub fn members(&self) -> &[(String, RecipientKey)] {
+        &self.members
+    }
+
+    /// Return the number of members.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Return whether the list has no members.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
     }
 }
 
@@ -416,6 +1310,66 @@ fn key_to_hex<S: Serializer>(val: &Key, serializer: S) -> Result<S::Ok, S::Error
     serializer.serialize_str(&HEXLOWER.encode(&val.0))
 }
 
+#[cfg(feature = "serde")]
+fn deserialize_from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let val = String::deserialize(deserializer)?;
+    val.parse().map_err(de::Error::custom)
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_opt_from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(val) => val.parse().map(Some).map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn key_from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Key, D::Error> {
+    let bytes: Vec<u8> = serde_hex::deserialize(deserializer)?;
+    Key::from_slice(&bytes).ok_or_else(|| de::Error::custom("Invalid key length"))
+}
+
+/// Serialize byte fields as lowercase hex strings.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_hex {
+    use std::convert::TryFrom;
+
+    use data_encoding::{HEXLOWER, HEXLOWER_PERMISSIVE};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S, T>(val: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: AsRef<[u8]>,
+    {
+        serializer.serialize_str(&HEXLOWER.encode(val.as_ref()))
+    }
+
+    pub(crate) fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: TryFrom<Vec<u8>>,
+    {
+        let val = String::deserialize(deserializer)?;
+        let bytes = HEXLOWER_PERMISSIVE
+            .decode(val.as_bytes())
+            .map_err(de::Error::custom)?;
+        let len = bytes.len();
+        T::try_from(bytes).map_err(|_| de::Error::custom(format!("Invalid byte length: {}", len)))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
@@ -424,6 +1378,43 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_required_capability() {
+        assert_eq!(MessageType::Text.required_capability(), None);
+        assert_eq!(MessageType::Location.required_capability(), None);
+        assert_eq!(MessageType::File.required_capability(), Some("file"));
+        assert_eq!(MessageType::Audio.required_capability(), Some("audio"));
+        assert_eq!(MessageType::GroupImage.required_capability(), Some("group"));
+        assert_eq!(
+            MessageType::ForwardSecurityEnvelope.required_capability(),
+            Some("pfs")
+        );
+    }
+
+    #[test]
+    fn test_distribution_list() {
+        let key_a = RecipientKey::from_bytes(&[1; 32]).unwrap();
+        let key_b = RecipientKey::from_bytes(&[2; 32]).unwrap();
+        let mut list = DistributionList::new("Alerts");
+        assert_eq!(list.name(), "Alerts");
+        assert!(list.is_empty());
+
+        list.add_member("AAAAAAAA", key_a.clone());
+        list.add_member("BBBBBBBB", key_b.clone());
+        list.add_member("AAAAAAAA", key_b.clone());
+        assert_eq!(
+            list.members(),
+            &[
+                ("AAAAAAAA".to_string(), key_b.clone()),
+                ("BBBBBBBB".to_string(), key_b)
+            ]
+        );
+
+        assert!(list.remove_member("BBBBBBBB"));
+        assert!(!list.remove_member("BBBBBBBB"));
+        assert_eq!(list.len(), 1);
+    }
+
     #[test]
     fn test_blob_id_from_str() {
         assert!(BlobId::from_str("0123456789abcdef0123456789abcdef").is_ok());
@@ -438,6 +1429,100 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_blob_id_roundtrip() {
+        let blob_id = BlobId::new([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0xa, 0xb, 0xc, 0xd, 0xe, 0xff]);
+        assert_eq!(blob_id.to_hex(), "000102030405060708090a0b0c0d0eff");
+        assert_eq!(blob_id.to_string(), blob_id.to_hex());
+        assert_eq!(BlobId::from_str(&blob_id.to_hex()).unwrap(), blob_id);
+        assert_eq!(blob_id.to_base64(), "AAECAwQFBgcICQoLDA0O/w==");
+        assert_eq!(BlobId::from_base64(&blob_id.to_base64()).unwrap(), blob_id);
+        assert_eq!(blob_id.as_bytes()[15], 0xff);
+        assert!(matches!(
+            BlobId::from_base64("AAECAwQFBgcICQoLDA0O"),
+            Err(ApiError::BadBlobId)
+        ));
+    }
+
+    #[test]
+    fn test_message_id_from_str() {
+        assert!(MessageId::from_str("0123456789abcdef").is_ok());
+        assert!(MessageId::from_str("0123456789ABCDEF").is_ok());
+        assert!(MessageId::from_str("0123456789abcde").is_err());
+        assert!(MessageId::from_str("0123456789abcdef00").is_err());
+        assert!(MessageId::from_str("0123456789abcdeg").is_err());
+
+        let id = MessageId::from_str("00010203040506ff").unwrap();
+        assert_eq!(id, MessageId::new([0, 1, 2, 3, 4, 5, 6, 0xff]));
+        assert_eq!(id.to_string(), "00010203040506ff");
+        assert_eq!(id.as_hex(), "00010203040506ff");
+    }
+
+    #[test]
+    fn test_delivery_receipt_roundtrip() {
+        let receipt = DeliveryReceipt::new(
+            ReceiptStatus::Read,
+            vec![
+                MessageId::new([1, 2, 3, 4, 5, 6, 7, 8]),
+                MessageId::new([9, 10, 11, 12, 13, 14, 15, 16]),
+            ],
+        );
+        let bytes = receipt.to_bytes();
+        assert_eq!(bytes.len(), 17);
+        assert_eq!(bytes[0], 0x02);
+        assert_eq!(&bytes[9..], &[9, 10, 11, 12, 13, 14, 15, 16]);
+        assert_eq!(DeliveryReceipt::from_bytes(&bytes).unwrap(), receipt);
+    }
+
+    #[test]
+    fn test_delivery_receipt_invalid() {
+        assert!(DeliveryReceipt::from_bytes(&[]).is_err());
+        assert!(DeliveryReceipt::from_bytes(&[0x01]).is_err());
+        assert!(DeliveryReceipt::from_bytes(&[0x01, 1, 2, 3]).is_err());
+        assert!(DeliveryReceipt::from_bytes(&[0x05, 1, 2, 3, 4, 5, 6, 7, 8]).is_err());
+    }
+
+    #[test]
+    fn test_image_message_builder() {
+        let blob_id = BlobId::new([1; 16]);
+        let msg = ImageMessage::builder()
+            .blob_id(blob_id.clone())
+            .size(1234)
+            .nonce([2; 24])
+            .build()
+            .unwrap();
+        assert_eq!(msg.blob_id(), &blob_id);
+        assert_eq!(msg.img_size_bytes(), 1234);
+        assert_eq!(msg.image_data_nonce(), &[2; 24]);
+
+        match ImageMessage::builder()
+            .blob_id(blob_id)
+            .nonce([2; 24])
+            .build()
+        {
+            Err(ImageMessageBuilderError::MissingField("size")) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_credits() {
+        let credits = Credits::from_str("1234\n").unwrap();
+        assert_eq!(credits, Credits(1234));
+        assert_eq!(credits.to_string(), "1234");
+        assert!(credits.is_low(2000));
+        assert!(!credits.is_low(1234));
+        assert!(Credits::from_str("-1").is_err());
+        assert!(Credits::from_str("many").is_err());
+    }
+
+    #[test]
+    fn test_cost_of() {
+        assert_eq!(cost_of(0, 0), Credits(0));
+        assert_eq!(cost_of(10, 2), Credits(12));
+        assert!(Credits(11) < cost_of(10, 2));
+    }
+
     #[test]
     fn test_serialize_to_string_minimal() {
         let pk = Key([
@@ -453,6 +1538,7 @@ mod test {
             file_name: None,
             file_size_bytes: 2048,
             description: None,
+            correlation_id: None,
             rendering_type: RenderingType::File,
             reserved: 0,
             metadata: None,
@@ -476,6 +1562,7 @@ mod test {
         assert_eq!(deserialized.get("j").unwrap(), 0);
         assert_eq!(deserialized.get("i").unwrap(), 0);
         assert_eq!(deserialized.get("d"), None);
+        assert_eq!(deserialized.get("c"), None);
     }
 
     #[test]
@@ -493,6 +1580,7 @@ mod test {
             file_name: Some("secret.pdf".into()),
             file_size_bytes: 2048,
             description: Some("This is a fancy file".into()),
+            correlation_id: Some("abc123".into()),
             rendering_type: RenderingType::Sticker,
             reserved: 1,
             metadata: Some(FileMetadata {
@@ -505,7 +1593,7 @@ mod test {
         let data = json::to_string(&msg).unwrap();
         let deserialized: HashMap<String, json::Value> = json::from_str(&data).unwrap();
 
-        assert_eq!(deserialized.keys().len(), 11);
+        assert_eq!(deserialized.keys().len(), 12);
         assert_eq!(
             deserialized.get("b").unwrap(),
             "0123456789abcdef0123456789abcdef"
@@ -525,6 +1613,7 @@ mod test {
         assert_eq!(deserialized.get("j").unwrap(), 2);
         assert_eq!(deserialized.get("i").unwrap(), 1);
         assert_eq!(deserialized.get("d").unwrap(), "This is a fancy file");
+        assert_eq!(deserialized.get("c").unwrap(), "abc123");
         assert_eq!(deserialized.get("x").unwrap().get("a").unwrap(), true);
         assert_eq!(deserialized.get("x").unwrap().get("h").unwrap(), 320);
         assert_eq!(deserialized.get("x").unwrap().get("w").unwrap(), 240);
@@ -544,7 +1633,8 @@ mod test {
         let msg = FileMessage::builder(file_blob_id.clone(), key.clone(), jpeg.clone(), 2048)
             .thumbnail(thumb_blob_id.clone(), png.clone())
             .file_name("hello.jpg")
-            .description(String::from("An image file"))
+            .caption(String::from("An image file"))
+            .correlation_id("group-1")
             .rendering_type(RenderingType::Media)
             .build()
             .unwrap();
@@ -557,7 +1647,153 @@ mod test {
         assert_eq!(msg.file_name, Some("hello.jpg".to_string()));
         assert_eq!(msg.file_size_bytes, 2048);
         assert_eq!(msg.description, Some("An image file".to_string()));
+        assert_eq!(msg.correlation_id, Some("group-1".to_string()));
         assert_eq!(msg.rendering_type, RenderingType::Media);
         assert_eq!(msg.reserved, 1);
     }
+
+    #[test]
+    fn test_ballot_message_serialization() {
+        let msg = BallotMessage::builder("Lunch?")
+            .ballot_id([1, 2, 3, 4, 5, 6, 7, 8])
+            .choice("Pizza")
+            .choices(vec!["Sushi", "Salad"])
+            .assessment(BallotAssessment::MultipleChoice)
+            .ballot_type(BallotType::Intermediate)
+            .build()
+            .unwrap();
+        assert_eq!(msg.ballot_id(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let data: json::Value = json::from_str(&json::to_string(&msg).unwrap()).unwrap();
+        assert_eq!(
+            data,
+            json::json!({
+                "d": "Lunch?",
+                "s": 0,
+                "a": 1,
+                "t": 1,
+                "o": 0,
+                "c": [
+                    {"i": 0, "n": "Pizza", "o": 0, "r": []},
+                    {"i": 1, "n": "Sushi", "o": 1, "r": []},
+                    {"i": 2, "n": "Salad", "o": 2, "r": []},
+                ],
+                "p": [],
+            })
+        );
+    }
+
+    #[test]
+    fn test_ballot_message_builder_validation() {
+        assert!(BallotMessage::builder("Lunch?")
+            .choice("Pizza")
+            .build()
+            .is_err());
+        assert!(BallotMessage::builder(" ")
+            .choices(vec!["Pizza", "Sushi"])
+            .build()
+            .is_err());
+        let a = BallotMessage::builder("Lunch?")
+            .choices(vec!["Pizza", "Sushi"])
+            .build()
+            .unwrap();
+        let b = BallotMessage::builder("Lunch?")
+            .choices(vec!["Pizza", "Sushi"])
+            .build()
+            .unwrap();
+        assert_ne!(a.ballot_id(), b.ballot_id());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_blob_id_serde() {
+        let blob_id = BlobId::from_str("0123456789abcdef0123456789abcdef").unwrap();
+        let serialized = json::to_string(&blob_id).unwrap();
+        assert_eq!(serialized, "\"0123456789abcdef0123456789abcdef\"");
+        assert_eq!(json::from_str::<BlobId>(&serialized).unwrap(), blob_id);
+        assert!(json::from_str::<BlobId>("\"0123\"").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_file_message_serde_roundtrip() {
+        let msg = FileMessage::builder(
+            BlobId::from_str("0123456789abcdef0123456789abcdef").unwrap(),
+            Key([7; 32]),
+            "image/jpeg".parse().unwrap(),
+            2048,
+        )
+        .thumbnail(
+            BlobId::from_str("abcdef0123456789abcdef0123456789").unwrap(),
+            "image/png".parse().unwrap(),
+        )
+        .file_name("photo.jpg")
+        .rendering_type(RenderingType::Media)
+        .dimensions(100, 200)
+        .build()
+        .unwrap();
+        let serialized = json::to_string(&msg).unwrap();
+        let deserialized: FileMessage = json::from_str(&serialized).unwrap();
+        assert_eq!(json::to_string(&deserialized).unwrap(), serialized);
+        assert_eq!(deserialized.rendering_type, RenderingType::Media);
+        assert_eq!(deserialized.thumbnail_media_type, Some(mime::IMAGE_PNG));
+    }
+
+    #[test]
+    fn test_fs_control_message_to_bytes() {
+        let session_id = FsSessionId([0x11; 16]);
+        let terminate = FsControlMessage::Terminate {
+            session_id,
+            cause: FsTerminateCause::Reset,
+        };
+        let mut expected = vec![0x0a, 16];
+        expected.extend_from_slice(&[0x11; 16]);
+        expected.extend_from_slice(&[0x2a, 2, 0x08, 1]);
+        assert_eq!(terminate.to_bytes(), expected);
+
+        let init = FsControlMessage::Init {
+            session_id,
+            ephemeral_public_key: [0x22; 32],
+        };
+        let bytes = init.to_bytes();
+        assert_eq!(bytes.len(), 2 + 16 + 2 + 2 + 32);
+        assert_eq!(&bytes[18..22], &[0x12, 34, 0x0a, 32]);
+        assert_eq!(&bytes[22..], &[0x22; 32][..]);
+
+        let reject = FsControlMessage::Reject {
+            session_id,
+            rejected_message_id: MessageId([1, 2, 3, 4, 5, 6, 7, 8]),
+            cause: FsRejectCause::UnknownSession,
+        };
+        assert_eq!(
+            &reject.to_bytes()[18..],
+            &[0x22, 11, 0x09, 1, 2, 3, 4, 5, 6, 7, 8, 0x10, 1]
+        );
+    }
+
+    #[test]
+    fn test_message_type_byte_roundtrip() {
+        let types = [
+            MessageType::Text,
+            MessageType::Image,
+            MessageType::Location,
+            MessageType::Video,
+            MessageType::Audio,
+            MessageType::File,
+            MessageType::BallotCreate,
+            MessageType::GroupText,
+            MessageType::GroupImage,
+            MessageType::GroupSetup,
+            MessageType::GroupName,
+            MessageType::GroupSetPhoto,
+            MessageType::GroupDeletePhoto,
+            MessageType::DeliveryReceipt,
+            MessageType::ForwardSecurityEnvelope,
+        ];
+        for msgtype in &types {
+            assert_eq!(MessageType::from_byte(msgtype.to_byte()), Some(*msgtype));
+        }
+        assert_eq!(MessageType::Text.to_byte(), 0x01);
+        assert_eq!(MessageType::from_byte(0x00), None);
+        assert_eq!(MessageType::from_byte(0xff), None);
+    }
 }